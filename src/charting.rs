@@ -0,0 +1,387 @@
+#![allow(clippy::too_many_arguments)]
+
+use super::{
+    chunk_management::{chunk_and_local_from_global, GeneratedChunks},
+    environment::{Sandstorms, TimeOfDay},
+    platform_modules::PlatformModules,
+    radio::RadioMessage,
+    terrain::TerrainMap,
+    world_seed::WorldSeed,
+    ChartRange, Chunk, ChunkPos, Crew, MapPos, PlayerVehicle, TileKind, TileVisibility,
+    PLATFORM_LOOKOUTS,
+};
+use bevy::{
+    prelude::*,
+    utils::{HashMap, HashSet},
+};
+use bevy_ecs_tilemap::{
+    helpers::hex_grid::{axial::AxialPos, offset::RowEvenPos},
+    prelude::*,
+};
+use serde::{Deserialize, Serialize};
+
+/// Tiles seen from the deck with bare eyes, and what every lookout adds
+const BASE_SIGHT: f32 = 2.0;
+const LOOKOUT_SIGHT: f32 = 1.0;
+/// Most crew that can be sent up to look out
+const MAX_LOOKOUTS: u32 = 2;
+/// Part of the sight that is left at dusk
+const DUSK_SIGHT: f32 = 0.6;
+/// Part of the sight and of the radar range that is left in a sandstorm
+const SANDSTORM_SIGHT: f32 = 0.4;
+const SANDSTORM_RADAR: f32 = 0.7;
+
+pub struct ChartingPlugin;
+
+impl Plugin for ChartingPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<VisibleTiles>()
+            .add_system(assign_lookouts)
+            .add_system(update_chart_range.after(assign_lookouts))
+            .add_system(chart_map.after(update_chart_range));
+    }
+}
+
+/// Crew members watching the horizon instead of working
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Lookouts(pub u32);
+
+impl Default for Lookouts {
+    /// Who watches when the platform sets out
+    fn default() -> Self {
+        Self(PLATFORM_LOOKOUTS)
+    }
+}
+
+/// Tiles seen around a vehicle. Lookouts see less when it's dark or the sand is in the air, the
+/// radar only suffers from the sand.
+pub fn chart_range(radar_range: f32, lookouts: u32, dusk: bool, sandstorm: bool) -> u32 {
+    let mut sight = BASE_SIGHT + LOOKOUT_SIGHT * lookouts as f32;
+    let mut radar = radar_range;
+    if dusk {
+        sight *= DUSK_SIGHT;
+    }
+    if sandstorm {
+        sight *= SANDSTORM_SIGHT;
+        radar *= SANDSTORM_RADAR;
+    }
+    ((sight + radar).round() as u32).max(1)
+}
+
+/// Tiles on a straight line between two tiles, both included
+fn hex_line(from: RowEvenPos, to: RowEvenPos) -> Vec<RowEvenPos> {
+    let a = AxialPos::from(from);
+    let b = AxialPos::from(to);
+    let distance = a.distance_from(&b);
+    // Nudged a bit so that lines along tile edges always pick the same side
+    let a = Vec3::new(
+        a.q as f32 + 1e-6,
+        a.r as f32 + 1e-6,
+        (-a.q - a.r) as f32 - 2e-6,
+    );
+    let b = Vec3::new(
+        b.q as f32 + 1e-6,
+        b.r as f32 + 1e-6,
+        (-b.q - b.r) as f32 - 2e-6,
+    );
+    (0..=distance)
+        .map(|step| {
+            let cube = a.lerp(b, step as f32 / distance.max(1) as f32);
+            let mut rounded = cube.round();
+            let diff = (rounded - cube).abs();
+            if diff.x > diff.y && diff.x > diff.z {
+                rounded.x = -rounded.y - rounded.z;
+            } else if diff.y > diff.z {
+                rounded.y = -rounded.x - rounded.z;
+            }
+            AxialPos {
+                q: rounded.x as i32,
+                r: rounded.y as i32,
+            }
+            .into()
+        })
+        .collect()
+}
+
+/// Whether a tile can be seen from another one. Rocky plateaus hide what is behind them, unless
+/// the observer is up on one.
+pub fn is_in_sight(from: RowEvenPos, to: RowEvenPos, terrain: &TerrainMap) -> bool {
+    if terrain.kind_at(from) == TileKind::RockyPlateau {
+        return true;
+    }
+    let line = hex_line(from, to);
+    line.iter()
+        .skip(1)
+        .take(line.len().saturating_sub(2))
+        .all(|pos| terrain.kind_at(*pos) != TileKind::RockyPlateau)
+}
+
+/// Sends more or fewer of the crew up to look out
+fn assign_lookouts(
+    input: Res<Input<KeyCode>>,
+    mut vehicles: Query<(&mut Lookouts, &Crew), With<PlayerVehicle>>,
+    mut radio: EventWriter<RadioMessage>,
+) {
+    if !input.just_pressed(KeyCode::L) {
+        return;
+    }
+    for (mut lookouts, crew) in vehicles.iter_mut() {
+        lookouts.0 = (lookouts.0 + 1) % (MAX_LOOKOUTS.min(crew.0) + 1);
+        radio.send(RadioMessage {
+            from: "Deck".to_string(),
+            text: format!("{} of the crew on lookout.", lookouts.0),
+        });
+    }
+}
+
+fn update_chart_range(
+    time_of_day: Res<TimeOfDay>,
+    sandstorms: Res<Sandstorms>,
+    mut vehicles: Query<(&mut ChartRange, &Lookouts, Option<&PlatformModules>)>,
+) {
+    for (mut range, lookouts, modules) in vehicles.iter_mut() {
+        let radar_range = modules.map_or(0.0, PlatformModules::radar_range);
+        let new_range = chart_range(
+            radar_range,
+            lookouts.0,
+            time_of_day.is_dusk(),
+            sandstorms.is_active(),
+        );
+        if range.0 != new_range {
+            range.0 = new_range;
+        }
+    }
+}
+
+/// Tiles that were visible the last time the map was charted
+#[derive(Resource, Default)]
+struct VisibleTiles(HashSet<RowEvenPos>);
+
+/// Marks tiles seen by any of the charting sources (vehicles, outposts, anything with a
+/// [`ChartRange`]) as visible and the ones that went out of sight as charted. Runs only when a
+/// source moves, its range changes, a source goes away or chunks get spawned, and touches only the
+/// tiles that were or are in sight.
+fn chart_map(
+    world_seed: Res<WorldSeed>,
+    generated_chunks: Res<GeneratedChunks>,
+    mut visible_tiles: ResMut<VisibleTiles>,
+    sources: Query<(&MapPos, &ChartRange)>,
+    changed_sources: Query<(), (With<ChartRange>, Or<(Changed<MapPos>, Changed<ChartRange>)>)>,
+    mut removed_sources: RemovedComponents<ChartRange>,
+    new_chunks: Query<(), Added<Chunk>>,
+    chunks: Query<(&Chunk, &TileStorage)>,
+    mut tiles: Query<&mut TileVisibility>,
+) {
+    let sources_removed = removed_sources.iter().count() > 0;
+    if changed_sources.is_empty() && !sources_removed && new_chunks.is_empty() {
+        return;
+    }
+    let terrain = TerrainMap::new(&world_seed, &generated_chunks);
+    let in_sight: HashSet<RowEvenPos> = sources
+        .iter()
+        .flat_map(|(source_pos, chart_range)| {
+            let from = source_pos.pos;
+            let terrain = &terrain;
+            generate_hexagon(from.into(), chart_range.0)
+                .into_iter()
+                .map(Into::into)
+                .filter(move |pos| is_in_sight(from, *pos, terrain))
+        })
+        .collect();
+    let tile_storages: HashMap<ChunkPos, &TileStorage> = chunks
+        .iter()
+        .map(|(chunk, tile_storage)| (chunk.pos, tile_storage))
+        .collect();
+    let tile_at = |pos: RowEvenPos| {
+        let (chunk_pos, tile_pos) = chunk_and_local_from_global(pos);
+        tile_storages
+            .get(&chunk_pos)
+            .and_then(|tile_storage| tile_storage.get(&tile_pos))
+    };
+
+    for pos in visible_tiles.0.difference(&in_sight) {
+        if let Some(mut tile_vis) = tile_at(*pos).and_then(|tile| tiles.get_mut(tile).ok()) {
+            *tile_vis = TileVisibility::Charted;
+        }
+    }
+    // Chunks spawned since last time start out unknown or charted, so every tile in sight is
+    // checked and not just the new ones
+    for pos in in_sight.iter() {
+        if let Some(mut tile_vis) = tile_at(*pos).and_then(|tile| tiles.get_mut(tile).ok()) {
+            if !matches!(*tile_vis, TileVisibility::Visible) {
+                *tile_vis = TileVisibility::Visible;
+            }
+        }
+    }
+    visible_tiles.0 = in_sight;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        chunk_management::{global_from_chunk_and_local, TILEMAP_CHUNK_SIZE},
+        neighbour,
+        test_support::test_world,
+    };
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn chart_range_depends_on_radar_lookouts_and_weather() {
+        // Bare eyes and lookouts
+        assert_eq!(chart_range(0.0, 0, false, false), 2);
+        assert_eq!(chart_range(0.0, 1, false, false), 3);
+        assert_eq!(chart_range(0.0, 2, false, false), 4);
+        // Radar in perfect condition and half worn out
+        assert_eq!(chart_range(4.0, 1, false, false), 7);
+        assert_eq!(chart_range(2.0, 1, false, false), 5);
+        // Dusk only dims the eyes, sand blinds the radar too
+        assert_eq!(chart_range(4.0, 1, true, false), 6);
+        assert_eq!(chart_range(4.0, 1, false, true), 4);
+        // Something is always seen
+        assert_eq!(chart_range(0.0, 0, true, true), 1);
+    }
+
+    #[test]
+    fn rocky_plateaus_hide_what_is_behind_them() {
+        let mut world = test_world(1);
+        let start = MapPos {
+            pos: RowEvenPos { q: 8, r: 8 },
+            ..default()
+        };
+        let ahead =
+            |steps| (0..steps).fold(start.pos, |pos, _| neighbour(pos, start.current_direction));
+        world
+            .generated_chunks
+            .set_kind(ahead(2), TileKind::RockyPlateau);
+        let terrain = world.terrain();
+
+        assert!(is_in_sight(start.pos, ahead(1), &terrain));
+        assert!(is_in_sight(start.pos, ahead(2), &terrain));
+        assert!(!is_in_sight(start.pos, ahead(4), &terrain));
+        // Up on the plateau, everything around is in sight
+        assert!(is_in_sight(ahead(2), start.pos, &terrain));
+        assert!(is_in_sight(ahead(2), ahead(4), &terrain));
+    }
+
+    /// Charting as it was before it looked tiles up through the tile storages, scanning every
+    /// loaded tile every frame. Only kept to compare against.
+    fn chart_map_by_scanning(
+        world_seed: Res<WorldSeed>,
+        generated_chunks: Res<GeneratedChunks>,
+        sources: Query<(&MapPos, &ChartRange)>,
+        mut tiles: Query<(&mut TileVisibility, &TilePos, &TilemapId)>,
+        chunks: Query<&Chunk>,
+    ) {
+        let (source_pos, chart_range) = sources.single();
+        let terrain = TerrainMap::new(&world_seed, &generated_chunks);
+        let tiles_in_chart_range: Vec<RowEvenPos> =
+            generate_hexagon(source_pos.pos.into(), chart_range.0)
+                .into_iter()
+                .map(Into::into)
+                .filter(|pos| is_in_sight(source_pos.pos, *pos, &terrain))
+                .collect();
+        for (mut tile_vis, tile_pos, tilemap_id) in tiles.iter_mut() {
+            let chunk = chunks.get(tilemap_id.0).unwrap();
+            let global_tile_pos = global_from_chunk_and_local(chunk.pos, *tile_pos);
+            if tiles_in_chart_range.contains(&global_tile_pos) {
+                *tile_vis = TileVisibility::Visible
+            } else if matches!(*tile_vis, TileVisibility::Visible) {
+                *tile_vis = TileVisibility::Charted
+            }
+        }
+    }
+
+    /// App with 7 by 7 loaded chunks around a vehicle in the middle of them
+    fn app_with_49_chunks<M>(charting: impl IntoSystemAppConfig<M>) -> App {
+        let world = test_world(3);
+        let mut app = App::new();
+        app.init_resource::<VisibleTiles>()
+            .insert_resource(world.world_seed)
+            .insert_resource(world.generated_chunks)
+            .add_system(charting);
+        for x in -3..=3 {
+            for y in -3..=3 {
+                let chunk = app.world.spawn_empty().id();
+                let mut tile_storage = TileStorage::empty(TILEMAP_CHUNK_SIZE);
+                for tile_x in 0..TILEMAP_CHUNK_SIZE.x {
+                    for tile_y in 0..TILEMAP_CHUNK_SIZE.y {
+                        let tile_pos = TilePos {
+                            x: tile_x,
+                            y: tile_y,
+                        };
+                        let tile = app
+                            .world
+                            .spawn((TileVisibility::Unknown, tile_pos, TilemapId(chunk)))
+                            .id();
+                        tile_storage.set(&tile_pos, tile);
+                    }
+                }
+                app.world.entity_mut(chunk).insert((
+                    Chunk {
+                        pos: ChunkPos::new(x, y),
+                    },
+                    tile_storage,
+                ));
+            }
+        }
+        app.world.spawn((
+            MapPos {
+                pos: RowEvenPos { q: 16, r: 16 },
+                ..default()
+            },
+            ChartRange(7),
+        ));
+        app.update();
+        app
+    }
+
+    /// Average time of a frame, with the vehicle moving a tile every frame or standing still
+    fn time_charting(app: &mut App, moving: bool) -> Duration {
+        const FRAMES: u32 = 100;
+        let start = Instant::now();
+        for frame in 0..FRAMES {
+            if moving {
+                let mut map_pos = app.world.query::<&mut MapPos>();
+                map_pos.single_mut(&mut app.world).pos.q = 16 + (frame % 2) as i32;
+            }
+            app.update();
+        }
+        start.elapsed() / FRAMES
+    }
+
+    /// Tiles that are visible and charted
+    fn visibility_counts(app: &mut App) -> (usize, usize) {
+        let mut tiles = app.world.query::<&TileVisibility>();
+        tiles
+            .iter(&app.world)
+            .fold((0, 0), |(visible, charted), tile_vis| match tile_vis {
+                TileVisibility::Visible => (visible + 1, charted),
+                TileVisibility::Charted => (visible, charted + 1),
+                TileVisibility::Unknown => (visible, charted),
+            })
+    }
+
+    /// Too slow for a debug build, run with `cargo test --release bench_charting -- --ignored`
+    #[test]
+    #[ignore]
+    fn bench_charting_with_49_chunks() {
+        let mut scanning = app_with_49_chunks(chart_map_by_scanning);
+        let mut storage_lookups = app_with_49_chunks(chart_map);
+        for moving in [true, false] {
+            let scanning_time = time_charting(&mut scanning, moving);
+            let storage_lookups_time = time_charting(&mut storage_lookups, moving);
+            assert!(
+                storage_lookups_time < scanning_time,
+                "Looking tiles up took {:?} per frame, scanning every tile {:?}",
+                storage_lookups_time,
+                scanning_time
+            );
+        }
+        // Both chart the same tiles, a hexagon with a radius of 7 is in sight on open sand
+        let counts = visibility_counts(&mut storage_lookups);
+        assert_eq!(counts, visibility_counts(&mut scanning));
+        assert_eq!(counts.0, 169);
+        assert!(counts.1 > 0);
+    }
+}
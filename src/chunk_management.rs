@@ -3,20 +3,33 @@
 use crate::SpriteAssets;
 
 use super::{
-    Chunk, ChunkPos, Map, MapPos, Npc, PlayerVehicle, TileKind, TileVisibility, WorldSeed,
+    deposits::{generate_deposits, ChunkDeposits},
+    terrain::TerrainNoise,
+    world_seed::{SeedDerivation, SeedPurpose},
+    Chunk, ChunkPos, Map, MapPos, TileKind, TileVisibility, WorldSeed,
 };
 use bevy::{
+    diagnostic::{Diagnostic, DiagnosticId, Diagnostics},
     prelude::*,
+    tasks::{AsyncComputeTaskPool, Task},
     utils::{HashMap, HashSet},
 };
 use bevy_ecs_tilemap::{helpers::hex_grid::offset::RowEvenPos, prelude::*};
-use rand::{distributions::WeightedIndex, prelude::*};
+use futures_lite::future;
+use rand::{distributions::WeightedIndex, prelude::*, rngs::SmallRng};
+use serde::{Deserialize, Serialize};
+use std::{cmp::Reverse, collections::BinaryHeap};
 
-// Test and adjust
-const PLAYER_CHUNK_LOAD_DISTANCE: i32 = 3;
-const PLAYER_CHUNK_UNLOAD_DISTANCE: i32 = 5;
-const NPC_CHUNK_LOAD_DISTANCE: i32 = 1;
-const NPC_CHUNK_UNLOAD_DISTANCE: i32 = 2;
+/// Most chunks loaded in a single frame, the rest wait in [`PendingChunks`]
+const CHUNK_SPAWN_BUDGET: usize = 2;
+
+/// Chunks left waiting to be loaded every frame. Logged by `LogDiagnosticsPlugin` when it is added
+/// to the app, or read from [`Diagnostics`].
+pub const PENDING_CHUNKS: DiagnosticId =
+    DiagnosticId::from_u128(0x6d65726b_6879_6c00_6368_756e6b730000);
+
+/// Chance of a habitable tile having a village
+const VILLAGE_CHANCE: f64 = 5.0 / 205.0;
 
 pub const TILEMAP_CHUNK_SIZE: TilemapSize = TilemapSize { x: 32, y: 32 };
 pub const TILEMAP_TILE_SIZE: TilemapTileSize = TilemapTileSize { x: 28.0, y: 32.0 };
@@ -29,32 +42,230 @@ impl Plugin for ChunkManagementPlugin {
     fn build(&self, app: &mut App) {
         app.insert_resource(LoadedChunks::default())
             .insert_resource(GeneratedChunks::default())
-            .add_system(load_chunks_player)
-            .add_system(load_chunks_npc.after(load_chunks_player))
-            .add_system(chunk_unload.after(load_chunks_npc));
+            .insert_resource(PendingChunks::default())
+            .insert_resource(GeneratingChunks::default())
+            .init_resource::<Diagnostics>()
+            .add_startup_system(setup_diagnostics)
+            .add_system(request_chunks)
+            .add_system(spawn_pending_chunks.after(request_chunks))
+            .add_system(poll_generating_chunks.after(spawn_pending_chunks))
+            .add_system(chunk_unload.after(poll_generating_chunks));
+    }
+}
+
+fn setup_diagnostics(mut diagnostics: ResMut<Diagnostics>) {
+    diagnostics.add(Diagnostic::new(PENDING_CHUNKS, "pending_chunks", 20));
+}
+
+/// Keeps chunks around the entity loaded. Radiuses are in chunks, chunks are unloaded only past
+/// the unload radius so that they don't flicker at the border. Entities on the map are loaders at
+/// their [`MapPos`], others at their translation in the map.
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChunkLoader {
+    pub load_radius: i32,
+    pub unload_radius: i32,
+    /// Whether the loaded chunks get a tilemap, which shows them on the map
+    pub renders: bool,
+    /// Whether the loaded chunks are kept for simulation even without a tilemap
+    pub simulates: bool,
+}
+
+impl ChunkLoader {
+    // Test and adjust
+    pub const PLAYER: Self = Self {
+        load_radius: 3,
+        unload_radius: 5,
+        renders: true,
+        simulates: true,
+    };
+    pub const NPC: Self = Self {
+        load_radius: 1,
+        unload_radius: 2,
+        renders: false,
+        simulates: true,
+    };
+    /// Shows what was charted wherever the map is panned to. Loads only chunks that were charted
+    /// or generated already, so that panning doesn't generate the world.
+    pub const MAP_CAMERA: Self = Self {
+        load_radius: 2,
+        unload_radius: 3,
+        renders: true,
+        simulates: false,
+    };
+}
+
+/// Chunk a loader is in
+fn loader_chunk_pos(map_pos: Option<&MapPos>, transform: Option<&Transform>) -> Option<ChunkPos> {
+    match (map_pos, transform) {
+        (Some(map_pos), _) => Some(chunk_and_local_from_global(map_pos.pos).0),
+        (None, Some(transform)) => Some(camera_to_chunk_pos(transform.translation.truncate())),
+        (None, None) => None,
+    }
+}
+
+/// Chunks loaded by anything. Only chunks loaded by rendering loaders get a tilemap, the rest are
+/// kept as data so that the map doesn't give away where npcs are.
+#[derive(Resource, Default)]
+pub struct LoadedChunks {
+    /// Chunks with a spawned tilemap
+    pub rendered: HashSet<ChunkPos>,
+    /// Chunks that are only generated and simulated, without a tilemap
+    pub simulated: HashSet<ChunkPos>,
+}
+
+impl LoadedChunks {
+    pub fn clear(&mut self) {
+        self.rendered.clear();
+        self.simulated.clear();
     }
+
+    fn contains(&self, pos: &ChunkPos) -> bool {
+        self.rendered.contains(pos) || self.simulated.contains(pos)
+    }
+}
+
+/// Chunks in range of a loader that are not loaded yet. Filled anew every frame, the nearest ones
+/// get loaded first.
+#[derive(Resource, Default)]
+pub struct PendingChunks {
+    /// Squared distance to the nearest loader of every missing chunk, and whether any of the
+    /// loaders wants it rendered
+    queue: HashMap<ChunkPos, (i32, bool)>,
+    /// How many chunks were left waiting after the last loading
+    pub waiting: usize,
 }
 
-/// Chunks loaded by anything. Chunks not loaded by a player should not be rendered to avoid seeing
-/// where npcs are
+impl PendingChunks {
+    fn request(&mut self, pos: ChunkPos, distance_squared: i32, render: bool) {
+        self.queue
+            .entry(pos)
+            .and_modify(|(queued_distance, queued_render)| {
+                *queued_distance = (*queued_distance).min(distance_squared);
+                *queued_render |= render;
+            })
+            .or_insert((distance_squared, render));
+    }
+}
+
+/// Chunks being generated on the async compute task pool
 #[derive(Resource, Default)]
-struct LoadedChunks(HashSet<ChunkPos>);
+pub struct GeneratingChunks(pub HashMap<ChunkPos, Task<GeneratedChunk>>);
+
+type GeneratedChunk = ([[TileKind; 32]; 32], ChunkDeposits);
+
+/// Chunk spawned before its generation finished, its tiles have no kind yet
+#[derive(Component)]
+struct PlaceholderChunk;
 
 #[derive(Resource, Debug, Clone, Default)]
-struct GeneratedChunks {
-    chunks: HashMap<ChunkPos, [[TileKind; 32]; 32]>,
+pub struct GeneratedChunks {
+    pub chunks: HashMap<ChunkPos, [[TileKind; 32]; 32]>,
+    /// Resource deposits, with how much was mined from them
+    pub deposits: HashMap<ChunkPos, ChunkDeposits>,
+    /// Tiles charted by the player, for chunks that have any
+    pub charted: HashMap<ChunkPos, ChartedTiles>,
+}
+
+#[cfg(test)]
+impl GeneratedChunks {
+    /// Chunks around the origin made of a single kind of tile, for tests that need known terrain
+    pub fn filled(kind: TileKind, radius: i32) -> Self {
+        let mut generated = Self::default();
+        for x in -radius..=radius {
+            for y in -radius..=radius {
+                generated
+                    .chunks
+                    .insert(ChunkPos::new(x, y), [[kind; 32]; 32]);
+            }
+        }
+        generated
+    }
+
+    pub fn set_kind(&mut self, pos: RowEvenPos, kind: TileKind) {
+        let (chunk_pos, tile_pos) = chunk_and_local_from_global(pos);
+        self.chunks
+            .entry(chunk_pos)
+            .or_insert([[TileKind::Empty; 32]; 32])[tile_pos.x as usize][tile_pos.y as usize] =
+            kind;
+    }
+}
+
+/// Which tiles of a chunk are charted, one bit per tile
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChartedTiles([u32; 32]);
+
+impl ChartedTiles {
+    pub fn get(&self, pos: TilePos) -> bool {
+        self.0[pos.x as usize] & (1 << pos.y) != 0
+    }
+
+    pub fn set(&mut self, pos: TilePos, charted: bool) {
+        if charted {
+            self.0[pos.x as usize] |= 1 << pos.y
+        } else {
+            self.0[pos.x as usize] &= !(1 << pos.y)
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.iter().all(|column| *column == 0)
+    }
+}
+
+/// Charted tiles of a spawned chunk, visible tiles count as charted
+pub fn charted_tiles(tile_storage: &TileStorage, tiles: &Query<&TileVisibility>) -> ChartedTiles {
+    let mut charted = ChartedTiles::default();
+    for x in 0..tile_storage.size.x {
+        for y in 0..tile_storage.size.y {
+            let tile_pos = TilePos { x, y };
+            let is_charted = tile_storage
+                .get(&tile_pos)
+                .and_then(|tile| tiles.get(tile).ok())
+                .is_some_and(|visibility| !matches!(visibility, TileVisibility::Unknown));
+            charted.set(tile_pos, is_charted);
+        }
+    }
+    charted
 }
 
-fn generate_chunk(world_seed: &[u8; 32], chunk_pos: ChunkPos) -> [[TileKind; 32]; 32] {
-    let mut chunk_seed = *world_seed;
-    chunk_seed[24..28].copy_from_slice(&chunk_pos.x.to_le_bytes());
-    chunk_seed[28..32].copy_from_slice(&chunk_pos.y.to_le_bytes());
-    let mut rng = SmallRng::from_seed(chunk_seed);
+pub fn generate_chunk(world_seed: &WorldSeed, chunk_pos: ChunkPos) -> [[TileKind; 32]; 32] {
+    match world_seed.derivation {
+        SeedDerivation::Legacy => scatter_villages(&mut SmallRng::from_seed(
+            world_seed.chunk_seed(chunk_pos, SeedPurpose::Terrain),
+        )),
+        SeedDerivation::V1 => {
+            scatter_villages(&mut world_seed.chunk_rng(chunk_pos, SeedPurpose::Terrain))
+        }
+        SeedDerivation::V2 => {
+            let terrain = TerrainNoise::new(world_seed);
+            let mut rng = world_seed.chunk_rng(chunk_pos, SeedPurpose::Villages);
+            std::array::from_fn(|x| {
+                std::array::from_fn(|y| {
+                    let tile_pos = TilePos {
+                        x: x as u32,
+                        y: y as u32,
+                    };
+                    let kind = terrain.terrain_at(global_from_chunk_and_local(chunk_pos, tile_pos));
+                    // Rolled for every tile so that terrain doesn't shift where villages are
+                    let village = rng.gen_bool(VILLAGE_CHANCE);
+                    if village && kind.is_habitable() {
+                        TileKind::Village
+                    } else {
+                        kind
+                    }
+                })
+            })
+        }
+    }
+}
+
+/// Open sand with villages scattered over it, the terrain of worlds without terrain noise
+fn scatter_villages(rng: &mut impl Rng) -> [[TileKind; 32]; 32] {
     let weights = [(TileKind::Empty, 200.0), (TileKind::Village, 5.0)];
     let dist = WeightedIndex::new(weights.iter().map(|item| item.1))
         .unwrap()
         .map(|i| weights[i].0);
-    std::array::from_fn(|_| std::array::from_fn(|_| dist.sample(&mut rng)))
+    std::array::from_fn(|_| std::array::from_fn(|_| dist.sample(rng)))
 }
 
 pub fn chunk_and_local_from_global(global_pos: RowEvenPos) -> (ChunkPos, TilePos) {
@@ -76,6 +287,15 @@ pub fn global_from_chunk_and_local(chunk: IVec2, local: TilePos) -> RowEvenPos {
     }
 }
 
+/// Tile entity at a global position, if its chunk is spawned
+pub fn find_tile(chunks: &Query<(&Chunk, &TileStorage)>, pos: RowEvenPos) -> Option<Entity> {
+    let (chunk_pos, tile_pos) = chunk_and_local_from_global(pos);
+    chunks
+        .iter()
+        .find(|(chunk, _)| chunk.pos == chunk_pos)
+        .and_then(|(_, tile_storage)| tile_storage.get(&tile_pos))
+}
+
 pub fn chunk_in_world_position(pos: ChunkPos) -> Vec2 {
     global_from_chunk_and_local(pos, TilePos { x: 0, y: 0 }).center_in_world(&TILEMAP_GRID_SIZE)
 }
@@ -88,50 +308,89 @@ pub fn chunk_center_position(pos: ChunkPos) -> Vec2 {
             .truncate()
 }
 
+pub fn world_to_global_pos(world_pos: Vec2) -> RowEvenPos {
+    RowEvenPos::from_world_pos(&world_pos, &TILEMAP_GRID_SIZE)
+}
+
 pub fn camera_to_chunk_pos(camera_pos: Vec2) -> ChunkPos {
-    let global_pos = RowEvenPos::from_world_pos(&camera_pos, &TILEMAP_GRID_SIZE);
-    chunk_and_local_from_global(global_pos).0
+    chunk_and_local_from_global(world_to_global_pos(camera_pos)).0
+}
+
+/// Size of a chunk in world space. Hex rows overlap, so chunks are wider than they are tall.
+fn chunk_world_size() -> Vec2 {
+    (chunk_in_world_position(IVec2::ONE) - chunk_in_world_position(IVec2::ZERO)).abs()
+}
+
+/// Squared straight line distance between chunk centers in world space, so that loaders load a circle and
+/// not an ellipse
+fn chunk_distance_squared(origin: ChunkPos, target: ChunkPos) -> i32 {
+    (chunk_center_position(target) - chunk_center_position(origin)).length_squared() as i32
+}
+
+/// Squared radius in world space, radiuses of loaders are counted in chunk widths
+fn radius_squared(radius: i32) -> i32 {
+    (radius as f32 * chunk_world_size().x).powi(2) as i32
 }
 
 pub fn is_chunk_in_radius(origin: ChunkPos, target: ChunkPos, radius: i32) -> bool {
-    ((origin.x - radius)..=(origin.x + radius)).contains(&target.x)
-        && ((origin.y - radius)..=(origin.y + radius)).contains(&target.y)
+    chunk_distance_squared(origin, target) <= radius_squared(radius)
 }
 
+/// Chunks within a radius around a chunk, paired with their squared distance to it
+fn chunks_in_radius(origin: ChunkPos, radius: i32) -> impl Iterator<Item = (ChunkPos, i32)> {
+    let chunk_size = chunk_world_size();
+    let rows = (radius as f32 * chunk_size.x / chunk_size.y).ceil() as i32;
+    ((origin.x - radius)..=(origin.x + radius))
+        .flat_map(move |x| ((origin.y - rows)..=(origin.y + rows)).map(move |y| IVec2::new(x, y)))
+        .map(move |pos| (pos, chunk_distance_squared(origin, pos)))
+        .filter(move |(_, distance_squared)| *distance_squared <= radius_squared(radius))
+}
+
+/// Spawns the tilemap of a chunk. Without generated data the tiles are placeholders that stay
+/// unknown until [`fill_placeholder_chunk`] gives them their kind and deposits.
 fn spawn_chunk(
     commands: &mut Commands,
     texture_handle: &Handle<Image>,
     pos: ChunkPos,
-    chunk_data: &[[TileKind; 32]; 32],
+    generated: Option<(&[[TileKind; 32]; 32], &ChunkDeposits)>,
+    charted: Option<&ChartedTiles>,
     map_entity: Entity,
 ) {
     commands
         .entity(map_entity)
         .with_children(|map_child_builder| {
             let mut tile_storage = TileStorage::empty(TILEMAP_CHUNK_SIZE);
-            map_child_builder
-                .spawn_empty()
+            let mut chunk_commands = map_child_builder.spawn_empty();
+            chunk_commands
                 .with_children(|cb| {
                     let tilemap_id = TilemapId(cb.parent_entity());
 
                     for x in 0..TILEMAP_CHUNK_SIZE.x {
                         for y in 0..TILEMAP_CHUNK_SIZE.y {
                             let pos = TilePos { x, y };
-                            let tile_entity = cb
-                                .spawn((
-                                    TileBundle {
-                                        position: pos,
-                                        texture_index: TileTextureIndex(0), // TODO,
-                                        tilemap_id,
-                                        visible: TileVisible(true),
-                                        flip: TileFlip::default(),
-                                        color: TileColor::default(),
-                                        old_position: TilePosOld::default(),
-                                    },
-                                    TileVisibility::Unknown,
-                                    chunk_data[x as usize][y as usize],
-                                ))
-                                .id();
+                            let mut tile_commands = cb.spawn((
+                                TileBundle {
+                                    position: pos,
+                                    texture_index: TileTextureIndex(0), // TODO,
+                                    tilemap_id,
+                                    visible: TileVisible(true),
+                                    flip: TileFlip::default(),
+                                    color: TileColor::default(),
+                                    old_position: TilePosOld::default(),
+                                },
+                                if charted.is_some_and(|charted| charted.get(pos)) {
+                                    TileVisibility::Charted
+                                } else {
+                                    TileVisibility::Unknown
+                                },
+                            ));
+                            if let Some((chunk_data, deposits)) = generated {
+                                tile_commands.insert(chunk_data[x as usize][y as usize]);
+                                if let Some(deposit) = deposits.get(pos) {
+                                    tile_commands.insert(*deposit);
+                                }
+                            }
+                            let tile_entity = tile_commands.id();
                             tile_storage.set(&pos, tile_entity);
                         }
                     }
@@ -151,104 +410,526 @@ fn spawn_chunk(
                     },
                     Chunk { pos },
                 ));
+            if generated.is_none() {
+                chunk_commands.insert(PlaceholderChunk);
+            }
         });
 }
 
-fn load_chunks_player(
+/// Queues the chunks that loaders want loaded and don't have yet. Loaders that don't simulate only
+/// want the chunks that were charted or generated already.
+fn request_chunks(
+    loaders: Query<(&ChunkLoader, Option<&MapPos>, Option<&Transform>)>,
+    loaded_chunks: Res<LoadedChunks>,
+    generated_chunks: Res<GeneratedChunks>,
+    mut pending_chunks: ResMut<PendingChunks>,
+) {
+    for (loader, map_pos, transform) in loaders.iter() {
+        let Some(loader_chunk_pos) = loader_chunk_pos(map_pos, transform) else {
+            continue;
+        };
+        for (chunk_pos, distance_squared) in chunks_in_radius(loader_chunk_pos, loader.load_radius)
+        {
+            let known = generated_chunks.charted.contains_key(&chunk_pos)
+                || generated_chunks.chunks.contains_key(&chunk_pos);
+            let missing = if loader.renders {
+                (loader.simulates || known) && !loaded_chunks.rendered.contains(&chunk_pos)
+            } else {
+                loader.simulates && !loaded_chunks.contains(&chunk_pos)
+            };
+            if missing {
+                pending_chunks.request(chunk_pos, distance_squared, loader.renders);
+            }
+        }
+    }
+}
+
+/// Loads the nearest pending chunks, no more than the budget allows in one frame. Chunks that were
+/// never generated are sent off to be generated, rendered ones are spawned as placeholders
+/// meanwhile.
+fn spawn_pending_chunks(
     mut commands: Commands,
-    player_vehicles: Query<&MapPos, With<PlayerVehicle>>,
+    mut pending_chunks: ResMut<PendingChunks>,
     mut loaded_chunks: ResMut<LoadedChunks>,
     map_tile_texture: Res<SpriteAssets>,
     map_entity: Query<Entity, With<Map>>,
     mut generated_chunks: ResMut<GeneratedChunks>,
+    mut generating_chunks: ResMut<GeneratingChunks>,
     world_seed: Res<WorldSeed>,
+    mut diagnostics: ResMut<Diagnostics>,
 ) {
     let map_entity = map_entity.single();
-    for player_pos in player_vehicles.iter() {
-        let player_chunk_pos = chunk_and_local_from_global(player_pos.pos).0;
-        for x in (player_chunk_pos.x - PLAYER_CHUNK_LOAD_DISTANCE)
-            ..=(player_chunk_pos.x + PLAYER_CHUNK_LOAD_DISTANCE)
-        {
-            for y in (player_chunk_pos.y - PLAYER_CHUNK_LOAD_DISTANCE)
-                ..=(player_chunk_pos.y + PLAYER_CHUNK_LOAD_DISTANCE)
-            {
-                let chunk_pos = IVec2::new(x, y);
-                if !loaded_chunks.0.contains(&chunk_pos) {
-                    let chunk_data = generated_chunks
-                        .chunks
-                        .entry(chunk_pos)
-                        .or_insert_with(|| generate_chunk(&world_seed.seed, chunk_pos));
-                    spawn_chunk(
-                        &mut commands,
-                        &map_tile_texture.map_tiles,
-                        chunk_pos,
-                        chunk_data,
-                        map_entity,
-                    );
-                    loaded_chunks.0.insert(chunk_pos);
-                }
+    let generated_chunks = generated_chunks.as_mut();
+    let thread_pool = AsyncComputeTaskPool::get();
+    // Rendered chunks go before simulated ones at the same distance
+    let mut queue: BinaryHeap<Reverse<(i32, bool, i32, i32)>> = pending_chunks
+        .queue
+        .drain()
+        .map(|(pos, (distance_squared, render))| Reverse((distance_squared, !render, pos.x, pos.y)))
+        .collect();
+    for _ in 0..CHUNK_SPAWN_BUDGET {
+        let Some(Reverse((_, simulate_only, x, y))) = queue.pop() else {
+            break;
+        };
+        let chunk_pos = IVec2::new(x, y);
+        let generated = match generated_chunks.chunks.get(&chunk_pos) {
+            Some(chunk_data) => Some((
+                chunk_data,
+                &*generated_chunks
+                    .deposits
+                    .entry(chunk_pos)
+                    .or_insert_with(|| generate_deposits(&world_seed, chunk_pos, chunk_data)),
+            )),
+            None => {
+                generating_chunks.0.entry(chunk_pos).or_insert_with(|| {
+                    let world_seed = WorldSeed::clone(&world_seed);
+                    thread_pool.spawn(async move {
+                        let chunk_data = generate_chunk(&world_seed, chunk_pos);
+                        let deposits = generate_deposits(&world_seed, chunk_pos, &chunk_data);
+                        (chunk_data, deposits)
+                    })
+                });
+                None
             }
+        };
+        if simulate_only {
+            loaded_chunks.simulated.insert(chunk_pos);
+            continue;
         }
+        spawn_chunk(
+            &mut commands,
+            &map_tile_texture.map_tiles,
+            chunk_pos,
+            generated,
+            generated_chunks.charted.get(&chunk_pos),
+            map_entity,
+        );
+        loaded_chunks.simulated.remove(&chunk_pos);
+        loaded_chunks.rendered.insert(chunk_pos);
+    }
+    diagnostics.add_measurement(PENDING_CHUNKS, || queue.len() as f64);
+    if pending_chunks.waiting != queue.len() {
+        debug!("{} chunks waiting to be loaded", queue.len());
+        pending_chunks.waiting = queue.len();
     }
 }
 
-fn load_chunks_npc(
+/// Stores chunks that finished generating and fills in the placeholders of generated chunks
+fn poll_generating_chunks(
     mut commands: Commands,
-    npcs: Query<&MapPos, With<Npc>>,
-    mut loaded_chunks: ResMut<LoadedChunks>,
-    map_tile_texture: Res<SpriteAssets>,
-    map_entity: Query<Entity, With<Map>>,
+    mut generating_chunks: ResMut<GeneratingChunks>,
     mut generated_chunks: ResMut<GeneratedChunks>,
-    world_seed: Res<WorldSeed>,
+    placeholders: Query<(Entity, &Chunk, &TileStorage), With<PlaceholderChunk>>,
 ) {
-    let map_entity = map_entity.single();
-    for npc_map_pos in npcs.iter() {
-        let npc_chunk_pos = chunk_and_local_from_global(npc_map_pos.pos).0;
-        for x in (npc_chunk_pos.x - NPC_CHUNK_LOAD_DISTANCE)
-            ..=(npc_chunk_pos.x + NPC_CHUNK_LOAD_DISTANCE)
-        {
-            for y in (npc_chunk_pos.y - NPC_CHUNK_LOAD_DISTANCE)
-                ..=(npc_chunk_pos.y + NPC_CHUNK_LOAD_DISTANCE)
-            {
-                let chunk_pos = IVec2::new(x, y);
-                if !loaded_chunks.0.contains(&chunk_pos) {
-                    let chunk_data = generated_chunks
-                        .chunks
-                        .entry(chunk_pos)
-                        .or_insert_with(|| generate_chunk(&world_seed.seed, chunk_pos));
-                    spawn_chunk(
-                        &mut commands,
-                        &map_tile_texture.map_tiles,
-                        chunk_pos,
-                        chunk_data,
-                        map_entity,
-                    );
-                    loaded_chunks.0.insert(chunk_pos);
-                }
+    let generated_chunks = generated_chunks.as_mut();
+    generating_chunks.0.retain(
+        |chunk_pos, task| match future::block_on(future::poll_once(task)) {
+            Some((chunk_data, deposits)) => {
+                generated_chunks.chunks.insert(*chunk_pos, chunk_data);
+                generated_chunks.deposits.insert(*chunk_pos, deposits);
+                false
+            }
+            None => true,
+        },
+    );
+    for (chunk_entity, chunk, tile_storage) in placeholders.iter() {
+        if let (Some(chunk_data), Some(deposits)) = (
+            generated_chunks.chunks.get(&chunk.pos),
+            generated_chunks.deposits.get(&chunk.pos),
+        ) {
+            fill_placeholder_chunk(&mut commands, tile_storage, chunk_data, deposits);
+            commands.entity(chunk_entity).remove::<PlaceholderChunk>();
+        }
+    }
+}
+
+fn fill_placeholder_chunk(
+    commands: &mut Commands,
+    tile_storage: &TileStorage,
+    chunk_data: &[[TileKind; 32]; 32],
+    deposits: &ChunkDeposits,
+) {
+    for x in 0..TILEMAP_CHUNK_SIZE.x {
+        for y in 0..TILEMAP_CHUNK_SIZE.y {
+            let pos = TilePos { x, y };
+            let Some(tile) = tile_storage.get(&pos) else {
+                continue;
+            };
+            let mut tile_commands = commands.entity(tile);
+            tile_commands.insert(chunk_data[x as usize][y as usize]);
+            if let Some(deposit) = deposits.get(pos) {
+                tile_commands.insert(*deposit);
             }
         }
     }
 }
 
+/// Despawns tilemaps of chunks that are far from all rendering loaders and forgets chunks that
+/// are far from every simulating loader. Chunks left only near simulating loaders keep being
+/// simulated.
 fn chunk_unload(
     mut commands: Commands,
-    player_vehicles: Query<&MapPos, With<PlayerVehicle>>,
-    npcs: Query<&MapPos, With<Npc>>,
-    chunks: Query<(Entity, &Chunk)>,
+    loaders: Query<(&ChunkLoader, Option<&MapPos>, Option<&Transform>)>,
+    chunks: Query<(Entity, &Chunk, &TileStorage)>,
+    tiles: Query<&TileVisibility>,
     mut loaded_chunks: ResMut<LoadedChunks>,
+    mut generated_chunks: ResMut<GeneratedChunks>,
+    mut generating_chunks: ResMut<GeneratingChunks>,
 ) {
-    for (chunk_entity, Chunk { pos: chunk_pos }) in chunks.iter() {
-        let mut player_chunk_positions = player_vehicles
-            .iter()
-            .map(|mp| chunk_and_local_from_global(mp.pos).0);
-        let mut npcs_chunk_positions = npcs.iter().map(|mp| chunk_and_local_from_global(mp.pos).0);
-        if !(player_chunk_positions
-            .any(|p| is_chunk_in_radius(p, *chunk_pos, PLAYER_CHUNK_UNLOAD_DISTANCE))
-            || npcs_chunk_positions
-                .any(|p| is_chunk_in_radius(p, *chunk_pos, NPC_CHUNK_UNLOAD_DISTANCE)))
-        {
-            commands.entity(chunk_entity).despawn_recursive();
-            loaded_chunks.0.remove(chunk_pos);
+    let loader_positions: Vec<(ChunkLoader, ChunkPos)> = loaders
+        .iter()
+        .filter_map(|(loader, map_pos, transform)| {
+            loader_chunk_pos(map_pos, transform).map(|pos| (*loader, pos))
+        })
+        .collect();
+    let keeps_loaded = |chunk_pos: ChunkPos, rendered: bool| {
+        loader_positions.iter().any(|(loader, loader_chunk_pos)| {
+            let keeps = if rendered {
+                loader.renders
+            } else {
+                loader.simulates
+            };
+            keeps && is_chunk_in_radius(*loader_chunk_pos, chunk_pos, loader.unload_radius)
+        })
+    };
+
+    for (chunk_entity, Chunk { pos: chunk_pos }, tile_storage) in chunks.iter() {
+        if keeps_loaded(*chunk_pos, true) {
+            continue;
+        }
+        // Remember what was charted for when the chunk is rendered again
+        let charted = charted_tiles(tile_storage, &tiles);
+        if charted.is_empty() {
+            generated_chunks.charted.remove(chunk_pos);
+        } else {
+            generated_chunks.charted.insert(*chunk_pos, charted);
         }
+        commands.entity(chunk_entity).despawn_recursive();
+        loaded_chunks.rendered.remove(chunk_pos);
+        if keeps_loaded(*chunk_pos, false) {
+            loaded_chunks.simulated.insert(*chunk_pos);
+        } else {
+            // Dropping the task cancels the generation
+            generating_chunks.0.remove(chunk_pos);
+        }
+    }
+    loaded_chunks.simulated.retain(|chunk_pos| {
+        let keep = keeps_loaded(*chunk_pos, false);
+        if !keep {
+            generating_chunks.0.remove(chunk_pos);
+        }
+        keep
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::test_seed;
+    use bevy::tasks::TaskPool;
+
+    /// App with only chunk management, around chunks that are already generated
+    fn test_app(generated_chunks: GeneratedChunks) -> App {
+        AsyncComputeTaskPool::init(TaskPool::new);
+        let mut app = App::new();
+        app.add_plugin(ChunkManagementPlugin)
+            .insert_resource(generated_chunks)
+            .insert_resource(test_seed())
+            .insert_resource(SpriteAssets {
+                mining_platform: default(),
+                map_tiles: default(),
+            });
+        app.world.spawn(Map);
+        app
+    }
+
+    fn tile_entity(app: &mut App, chunk_pos: ChunkPos, tile_pos: TilePos) -> Entity {
+        let mut chunks = app.world.query::<(&Chunk, &TileStorage)>();
+        chunks
+            .iter(&app.world)
+            .find(|(chunk, _)| chunk.pos == chunk_pos)
+            .and_then(|(_, tile_storage)| tile_storage.get(&tile_pos))
+            .expect("Chunk is not rendered")
+    }
+
+    fn chunk_count(app: &mut App) -> usize {
+        app.world.query::<&Chunk>().iter(&app.world).count()
+    }
+
+    fn tile_visibility(app: &mut App, chunk_pos: ChunkPos, tile_pos: TilePos) -> TileVisibility {
+        let tile = tile_entity(app, chunk_pos, tile_pos);
+        *app.world.get::<TileVisibility>(tile).unwrap()
+    }
+
+    #[test]
+    fn charted_tiles_survive_unloading() {
+        let mut app = test_app(GeneratedChunks::filled(TileKind::Empty, 0));
+        let chunk_pos = ChunkPos::new(0, 0);
+        let tile_pos = TilePos { x: 5, y: 7 };
+        let loader = app
+            .world
+            .spawn((MapPos::default(), ChunkLoader::MAP_CAMERA))
+            .id();
+        app.update();
+        assert!(matches!(
+            tile_visibility(&mut app, chunk_pos, tile_pos),
+            TileVisibility::Unknown
+        ));
+        let tile = tile_entity(&mut app, chunk_pos, tile_pos);
+        *app.world.get_mut::<TileVisibility>(tile).unwrap() = TileVisibility::Charted;
+
+        app.world.entity_mut(loader).remove::<ChunkLoader>();
+        app.update();
+        assert!(app
+            .world
+            .query::<&Chunk>()
+            .iter(&app.world)
+            .next()
+            .is_none());
+
+        app.world.entity_mut(loader).insert(ChunkLoader::MAP_CAMERA);
+        app.update();
+        assert!(matches!(
+            tile_visibility(&mut app, chunk_pos, tile_pos),
+            TileVisibility::Charted
+        ));
+        assert!(matches!(
+            tile_visibility(&mut app, chunk_pos, TilePos { x: 6, y: 7 }),
+            TileVisibility::Unknown
+        ));
+    }
+
+    #[test]
+    fn waiting_chunks_are_measured() {
+        let mut app = test_app(GeneratedChunks::filled(TileKind::Empty, 3));
+        app.world.spawn((MapPos::default(), ChunkLoader::PLAYER));
+        app.update();
+        let waiting = app.world.resource::<PendingChunks>().waiting;
+        assert!(waiting > 0);
+        let diagnostics = app.world.resource::<Diagnostics>();
+        assert_eq!(
+            diagnostics.get_measurement(PENDING_CHUNKS).unwrap().value,
+            waiting as f64
+        );
+    }
+
+    #[test]
+    fn npc_chunks_are_simulated_without_a_tilemap() {
+        let mut app = test_app(GeneratedChunks::filled(TileKind::Empty, 2));
+        app.world.spawn((MapPos::default(), ChunkLoader::NPC));
+        for _ in 0..5 {
+            app.update();
+        }
+        assert!(app
+            .world
+            .resource::<LoadedChunks>()
+            .simulated
+            .contains(&ChunkPos::new(0, 0)));
+        assert!(app.world.resource::<LoadedChunks>().rendered.is_empty());
+        assert!(app
+            .world
+            .query_filtered::<(), Or<(With<Chunk>, With<TileStorage>)>>()
+            .iter(&app.world)
+            .next()
+            .is_none());
+    }
+
+    #[test]
+    fn map_camera_does_not_generate_chunks() {
+        let mut app = test_app(GeneratedChunks::filled(TileKind::Empty, 0));
+        app.world
+            .spawn((MapPos::default(), ChunkLoader::MAP_CAMERA));
+        for _ in 0..15 {
+            app.update();
+        }
+        assert!(app.world.resource::<GeneratingChunks>().0.is_empty());
+        let generated_chunks = app.world.resource::<GeneratedChunks>();
+        assert_eq!(generated_chunks.chunks.len(), 1);
+        let loaded_chunks = app.world.resource::<LoadedChunks>();
+        assert_eq!(
+            loaded_chunks.rendered,
+            HashSet::from_iter([ChunkPos::new(0, 0)])
+        );
+    }
+
+    #[test]
+    fn legacy_seed_from_config_generates_the_old_chunks() {
+        let config_seed = "legacy:552CD7D00FA463D80D52103BFCFD1974A4022E5F556354D50000000000000000";
+        let world_seed: WorldSeed = config_seed.parse().unwrap();
+        for chunk_pos in [ChunkPos::new(0, 0), ChunkPos::new(3, -2)] {
+            // Generation as it was before seeds were derived
+            let mut chunk_seed = world_seed.seed;
+            chunk_seed[24..28].copy_from_slice(&chunk_pos.x.to_le_bytes());
+            chunk_seed[28..32].copy_from_slice(&chunk_pos.y.to_le_bytes());
+            let mut rng = SmallRng::from_seed(chunk_seed);
+            let weights = [(TileKind::Empty, 200.0), (TileKind::Village, 5.0)];
+            let dist = WeightedIndex::new(weights.iter().map(|item| item.1))
+                .unwrap()
+                .map(|i| weights[i].0);
+            let old_chunk: [[TileKind; 32]; 32] =
+                std::array::from_fn(|_| std::array::from_fn(|_| dist.sample(&mut rng)));
+
+            assert_eq!(generate_chunk(&world_seed, chunk_pos), old_chunk);
+        }
+    }
+
+    #[test]
+    fn same_seed_generates_the_same_chunk() {
+        let chunk_pos = ChunkPos::new(-4, 9);
+        let chunk = generate_chunk(&test_seed(), chunk_pos);
+        assert_eq!(generate_chunk(&test_seed(), chunk_pos), chunk);
+        let other_seed = WorldSeed {
+            seed: [8; 32],
+            ..test_seed()
+        };
+        assert_ne!(generate_chunk(&other_seed, chunk_pos), chunk);
+    }
+
+    #[test]
+    fn loaded_area_is_round_in_the_world() {
+        let mut app = test_app(GeneratedChunks::filled(TileKind::Empty, 8));
+        app.world.spawn((
+            MapPos::default(),
+            ChunkLoader {
+                load_radius: 6,
+                unload_radius: 7,
+                ..ChunkLoader::NPC
+            },
+        ));
+        for _ in 0..100 {
+            app.update();
+        }
+        let loaded_chunks = app.world.resource::<LoadedChunks>();
+        // 6 chunks across are as far as 7 chunks up
+        for x in [-6, 6] {
+            assert!(loaded_chunks.simulated.contains(&ChunkPos::new(x, 0)));
+        }
+        for y in [-7, 7] {
+            assert!(loaded_chunks.simulated.contains(&ChunkPos::new(0, y)));
+        }
+        assert!(!loaded_chunks.simulated.contains(&ChunkPos::new(7, 0)));
+        assert!(!loaded_chunks.simulated.contains(&ChunkPos::new(0, 8)));
+    }
+
+    /// Rendering loader of only the chunk it is in
+    const SINGLE_CHUNK: ChunkLoader = ChunkLoader {
+        load_radius: 0,
+        unload_radius: 0,
+        ..ChunkLoader::PLAYER
+    };
+
+    #[test]
+    fn placeholder_is_filled_once_generated() {
+        let mut app = test_app(GeneratedChunks::default());
+        app.world.spawn((MapPos::default(), SINGLE_CHUNK));
+        let chunk_pos = ChunkPos::new(0, 0);
+        let tile_pos = TilePos { x: 3, y: 9 };
+        app.update();
+        let mut placeholders = app.world.query_filtered::<&Chunk, With<PlaceholderChunk>>();
+        assert_eq!(placeholders.single(&app.world).pos, chunk_pos);
+        let tile = tile_entity(&mut app, chunk_pos, tile_pos);
+        assert!(app.world.get::<TileKind>(tile).is_none());
+
+        for _ in 0..1000 {
+            if placeholders.iter(&app.world).next().is_none() {
+                break;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(1));
+            app.update();
+        }
+        assert!(placeholders.iter(&app.world).next().is_none());
+        assert!(app.world.resource::<GeneratingChunks>().0.is_empty());
+        let generated_chunks = app.world.resource::<GeneratedChunks>();
+        let kind = generated_chunks.chunks[&chunk_pos][tile_pos.x as usize][tile_pos.y as usize];
+        let tile = tile_entity(&mut app, chunk_pos, tile_pos);
+        assert_eq!(app.world.get::<TileKind>(tile), Some(&kind));
+    }
+
+    #[test]
+    fn chunks_are_spawned_within_the_budget() {
+        let mut app = test_app(GeneratedChunks::filled(TileKind::Empty, 3));
+        app.world.spawn((MapPos::default(), ChunkLoader::PLAYER));
+        for frame in 1..=3 {
+            app.update();
+            assert_eq!(chunk_count(&mut app), frame * CHUNK_SPAWN_BUDGET);
+        }
+    }
+
+    #[test]
+    fn generation_is_cancelled_out_of_range() {
+        let mut app = test_app(GeneratedChunks::default());
+        let loader = app.world.spawn((MapPos::default(), SINGLE_CHUNK)).id();
+        app.update();
+        assert_eq!(chunk_count(&mut app), 1);
+
+        app.world.entity_mut(loader).remove::<ChunkLoader>();
+        app.update();
+        assert_eq!(chunk_count(&mut app), 0);
+        assert!(app.world.resource::<GeneratingChunks>().0.is_empty());
+        assert!(!app
+            .world
+            .resource::<LoadedChunks>()
+            .contains(&ChunkPos::new(0, 0)));
+    }
+
+    /// Loader position far from the origin and everything loaded around it
+    fn far_away() -> MapPos {
+        MapPos {
+            pos: RowEvenPos { q: 32 * 20, r: 0 },
+            ..default()
+        }
+    }
+
+    #[test]
+    fn chunk_stays_loaded_while_any_loader_is_near() {
+        let mut app = test_app(GeneratedChunks::filled(TileKind::Empty, 1));
+        let chunk_pos = ChunkPos::new(0, 0);
+        let first = app.world.spawn((MapPos::default(), ChunkLoader::NPC)).id();
+        let second = app.world.spawn((MapPos::default(), ChunkLoader::NPC)).id();
+        for _ in 0..5 {
+            app.update();
+        }
+        assert!(app
+            .world
+            .resource::<LoadedChunks>()
+            .simulated
+            .contains(&chunk_pos));
+
+        app.world.entity_mut(first).insert(far_away());
+        app.update();
+        assert!(app
+            .world
+            .resource::<LoadedChunks>()
+            .simulated
+            .contains(&chunk_pos));
+
+        app.world.entity_mut(second).insert(far_away());
+        app.update();
+        assert!(!app.world.resource::<LoadedChunks>().contains(&chunk_pos));
+    }
+
+    #[test]
+    fn rendered_chunk_is_still_simulated_near_an_npc() {
+        let mut app = test_app(GeneratedChunks::filled(TileKind::Empty, 1));
+        let chunk_pos = ChunkPos::new(0, 0);
+        let player = app
+            .world
+            .spawn((MapPos::default(), ChunkLoader::PLAYER))
+            .id();
+        app.world.spawn((MapPos::default(), ChunkLoader::NPC));
+        app.update();
+        assert!(app
+            .world
+            .resource::<LoadedChunks>()
+            .rendered
+            .contains(&chunk_pos));
+
+        app.world.entity_mut(player).insert(far_away());
+        app.update();
+        let mut chunks = app.world.query::<&Chunk>();
+        assert!(chunks.iter(&app.world).all(|chunk| chunk.pos != chunk_pos));
+        let loaded_chunks = app.world.resource::<LoadedChunks>();
+        assert!(!loaded_chunks.rendered.contains(&chunk_pos));
+        assert!(loaded_chunks.simulated.contains(&chunk_pos));
     }
 }
@@ -0,0 +1,587 @@
+use std::collections::VecDeque;
+
+use super::{
+    inventory::{Inventory, Item, Wallet},
+    navigation::RouteOrders,
+    platform_modules::{ModuleKind, PlatformModules},
+    radio::RadioMessage,
+    raiders::{RaiderCar, RaiderGang, BOARDERS_PER_CAR},
+    ui::{text_style, COMBAT_TEXT_COLOR},
+    Crew, FontAssets, MiningPlatform,
+};
+use bevy::prelude::*;
+use bevy_prototype_lyon::prelude::*;
+use rand::prelude::*;
+use rand_chacha::ChaCha8Rng;
+use serde::{Deserialize, Serialize};
+
+/// Hit points of the platform's hull
+pub const PLATFORM_MAX_HULL: u32 = 300;
+/// Seconds between combat rounds
+const COMBAT_ROUND: f32 = 1.5;
+/// Successful boardings it takes for raiders to get inside the platform
+const BOARDINGS_TO_TAKE: u32 = 3;
+/// Chance of a boarding attempt each round, before the crew fights it off
+const BOARDING_CHANCE: f32 = 0.1;
+/// Part of their cars raiders have to lose before they think of fleeing
+const FLEE_LOSSES: f32 = 0.5;
+/// Chance that a beaten gang flees in a round
+const FLEE_CHANCE: f32 = 0.3;
+/// Part of the cargo and money raiders take when they get inside
+const BOARDED_LOSS: f32 = 0.5;
+/// Scrap salvaged from a destroyed raider car
+const MIN_SALVAGE: u32 = 2;
+const MAX_SALVAGE: u32 = 6;
+/// How well the guns are aimed by hand, working turrets do better
+const MANUAL_AIM: f32 = 0.6;
+/// Part of the crew that fights when the living quarters are in ruins and nobody got rest
+const TIRED_DEFENDERS: f32 = 0.5;
+/// How many lines of the fight are shown
+const COMBAT_LOG_LINES: usize = 4;
+
+/// Where raider cars are shown around the platform in the platform view
+const CAR_POSITIONS: [Vec2; 6] = [
+    Vec2::new(-38.0, 12.0),
+    Vec2::new(38.0, -10.0),
+    Vec2::new(-38.0, -14.0),
+    Vec2::new(38.0, 14.0),
+    Vec2::new(-36.0, 0.0),
+    Vec2::new(36.0, 2.0),
+];
+const CAR_Z: f32 = 5.0;
+const CAR_COLOR: Color = Color::rgb(0.55, 0.3, 0.15);
+const WRECK_COLOR: Color = Color::rgb(0.2, 0.2, 0.2);
+
+pub struct CombatPlugin;
+
+impl Plugin for CombatPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system(start_encounters)
+            .add_system(fight.after(start_encounters))
+            .add_system(update_combat_scene.after(fight))
+            .add_system(despawn_combat_scenes.after(fight));
+    }
+}
+
+/// Hit points of a vehicle. The platform is never destroyed: at 0 hit points its hull is breached
+/// and raiders walk in, in the first round of every fight until the hull is patched.
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Hull {
+    pub hp: u32,
+    pub max_hp: u32,
+}
+
+impl Default for Hull {
+    fn default() -> Self {
+        Self {
+            hp: PLATFORM_MAX_HULL,
+            max_hp: PLATFORM_MAX_HULL,
+        }
+    }
+}
+
+/// Guns used by the platform and by raiders
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Weapon {
+    Rifles,
+    MountedGun,
+    Cannon,
+}
+
+impl Weapon {
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Rifles => "rifles",
+            Self::MountedGun => "mounted gun",
+            Self::Cannon => "cannon",
+        }
+    }
+
+    /// Least and most damage of a hit
+    fn damage(self) -> (u32, u32) {
+        match self {
+            Self::Rifles => (3, 6),
+            Self::MountedGun => (6, 12),
+            Self::Cannon => (14, 24),
+        }
+    }
+
+    /// Chance to hit in a round
+    fn accuracy(self) -> f32 {
+        match self {
+            Self::Rifles => 0.55,
+            Self::MountedGun => 0.5,
+            Self::Cannon => 0.35,
+        }
+    }
+
+    /// Damage of a hit, given a roll between 0 and 1
+    fn roll_damage(self, roll: f32) -> u32 {
+        let (min, max) = self.damage();
+        min + ((max - min + 1) as f32 * roll).min((max - min) as f32) as u32
+    }
+}
+
+/// Places on the platform where guns can be mounted, some may be empty
+#[derive(Component, Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WeaponMounts(pub Vec<Option<Weapon>>);
+
+impl Default for WeaponMounts {
+    /// What the convoy brought along
+    fn default() -> Self {
+        Self(vec![Some(Weapon::MountedGun), Some(Weapon::Rifles), None])
+    }
+}
+
+/// How a fight ended
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    /// Every raider car was destroyed
+    RaidersDestroyed,
+    /// Raiders ran away after losing too much
+    RaidersFled,
+    /// Raiders got inside and took what they could
+    PlatformBoarded,
+}
+
+/// Fight between the platform and a gang of raiders, on the gang's entity
+#[derive(Component, Debug, Default)]
+pub struct Encounter {
+    /// Rounds fought so far, each gets its own random stream
+    pub round: u64,
+    /// Seconds since the last round
+    since_round: f32,
+    /// Raiders that got onto the platform
+    pub boardings: u32,
+    /// Scrap that can be salvaged from destroyed cars
+    pub salvage: u32,
+    /// Latest things that happened
+    log: VecDeque<String>,
+}
+
+impl Encounter {
+    fn log(&mut self, line: String) {
+        info!("{}", line);
+        self.log.push_back(line);
+        if self.log.len() > COMBAT_LOG_LINES {
+            self.log.pop_front();
+        }
+    }
+
+    /// Fights one round. The same amount of numbers is rolled every round, so the fight goes the
+    /// same way for the same gang and platform. `aim` scales how often the platform's guns hit.
+    pub fn fight_round(
+        &mut self,
+        gang: &mut RaiderGang,
+        hull: &mut Hull,
+        mounts: &WeaponMounts,
+        aim: f32,
+        defenders: u32,
+    ) -> Option<Outcome> {
+        let mut rng = ChaCha8Rng::seed_from_u64(gang.seed);
+        rng.set_stream(self.round);
+        self.round += 1;
+
+        for mount in mounts.0.iter() {
+            let target = rng.gen_range(0..gang.cars.len());
+            let hit: f32 = rng.gen();
+            let damage: f32 = rng.gen();
+            let salvage = rng.gen_range(MIN_SALVAGE..=MAX_SALVAGE);
+            let Some(weapon) = mount else {
+                continue;
+            };
+            // Shooting at wrecks is pointless, the next car still driving is shot instead
+            let Some(target) = (0..gang.cars.len())
+                .map(|offset| (target + offset) % gang.cars.len())
+                .find(|index| !gang.cars[*index].is_destroyed())
+            else {
+                continue;
+            };
+            if hit >= weapon.accuracy() * aim {
+                continue;
+            }
+            let car = &mut gang.cars[target];
+            car.hp = car.hp.saturating_sub(weapon.roll_damage(damage));
+            if car.is_destroyed() {
+                self.salvage += salvage;
+                self.log(format!("Our {} wrecked a raider car", weapon.name()));
+            }
+        }
+
+        for car in gang.cars.iter() {
+            let hit: f32 = rng.gen();
+            let damage: f32 = rng.gen();
+            if car.is_destroyed() || hit >= car.weapon.accuracy() {
+                continue;
+            }
+            let damage = car.weapon.roll_damage(damage);
+            hull.hp = hull.hp.saturating_sub(damage);
+            self.log(format!(
+                "Raider {} hit the hull for {}",
+                car.weapon.name(),
+                damage
+            ));
+        }
+
+        let boarding: f32 = rng.gen();
+        let flee: f32 = rng.gen();
+        let driving = gang.driving();
+        if driving == 0 {
+            return Some(Outcome::RaidersDestroyed);
+        }
+        // Nothing keeps raiders out of a breached hull
+        if hull.hp == 0 {
+            return Some(Outcome::PlatformBoarded);
+        }
+        // More raiders against fewer defenders get on board more often
+        let boarders = driving * BOARDERS_PER_CAR;
+        if boarding < BOARDING_CHANCE * boarders as f32 / (boarders + defenders) as f32 {
+            self.boardings += 1;
+            self.log("Raiders climbed onto the deck".to_string());
+            if self.boardings >= BOARDINGS_TO_TAKE {
+                return Some(Outcome::PlatformBoarded);
+            }
+        }
+        let losses = 1.0 - driving as f32 / gang.cars.len() as f32;
+        if losses >= FLEE_LOSSES && flee < FLEE_CHANCE {
+            return Some(Outcome::RaidersFled);
+        }
+        None
+    }
+}
+
+/// Takes the spoils of a fight and reports how it ended, returns the message for the radio log.
+/// Money that doesn't fit in a wallet stays where it was.
+fn settle(
+    outcome: Outcome,
+    encounter: &Encounter,
+    gang: &mut RaiderGang,
+    gang_wallet: &mut Wallet,
+    inventory: &mut Inventory,
+    wallet: &mut Wallet,
+) -> String {
+    let salvage = encounter.salvage.min(inventory.space_for(Item::Scrap));
+    match outcome {
+        Outcome::RaidersDestroyed => {
+            inventory
+                .add(Item::Scrap, salvage)
+                .expect("Salvage is limited to what fits");
+            let looted = gang_wallet.0.min(u32::MAX - wallet.0);
+            wallet.0 += looted;
+            gang_wallet.0 -= looted;
+            format!(
+                "{} are no more. Salvaged {} scrap and {} money from the wrecks.",
+                gang.name, salvage, looted
+            )
+        }
+        Outcome::RaidersFled => {
+            inventory
+                .add(Item::Scrap, salvage)
+                .expect("Salvage is limited to what fits");
+            gang.fleeing = true;
+            format!(
+                "{} are running away. Salvaged {} scrap.",
+                gang.name, salvage
+            )
+        }
+        Outcome::PlatformBoarded => {
+            let taken: Vec<(Item, u32)> = inventory
+                .items()
+                .map(|(item, amount)| (item, (amount as f32 * BOARDED_LOSS) as u32))
+                .collect();
+            for (item, amount) in taken {
+                inventory
+                    .remove(item, amount)
+                    .expect("Only part of what is there is taken");
+            }
+            let money = ((wallet.0 as f32 * BOARDED_LOSS) as u32).min(u32::MAX - gang_wallet.0);
+            wallet.0 -= money;
+            gang_wallet.0 += money;
+            gang.fleeing = true;
+            format!(
+                "{} got inside and took half of the cargo and {} money.",
+                gang.name, money
+            )
+        }
+    }
+}
+
+/// Platform view of a fight: raider cars around the platform and a panel with how it goes
+#[derive(Component)]
+struct CombatScene {
+    gang: Entity,
+}
+
+#[derive(Component)]
+struct CombatCar {
+    index: usize,
+}
+
+#[derive(Component)]
+struct CombatPanel;
+
+fn spawn_combat_scene(
+    commands: &mut Commands,
+    fonts: &FontAssets,
+    gang_entity: Entity,
+    cars: &[RaiderCar],
+) {
+    commands
+        .spawn((CombatScene { gang: gang_entity }, SpatialBundle::default()))
+        .with_children(|scene| {
+            for (index, position) in CAR_POSITIONS.iter().take(cars.len()).enumerate() {
+                scene.spawn((
+                    CombatCar { index },
+                    ShapeBundle {
+                        path: GeometryBuilder::build_as(&shapes::Rectangle {
+                            extents: Vec2::new(6.0, 3.0),
+                            origin: RectangleOrigin::Center,
+                        }),
+                        transform: Transform::from_translation(position.extend(CAR_Z)),
+                        ..default()
+                    },
+                    Fill::color(CAR_COLOR),
+                ));
+            }
+        });
+    commands.spawn((
+        CombatScene { gang: gang_entity },
+        CombatPanel,
+        TextBundle::from_section(
+            "",
+            TextStyle {
+                color: COMBAT_TEXT_COLOR,
+                ..text_style(fonts)
+            },
+        )
+        .with_style(Style {
+            position_type: PositionType::Absolute,
+            position: UiRect {
+                left: Val::Px(10.0),
+                top: Val::Px(10.0),
+                ..default()
+            },
+            ..default()
+        }),
+    ));
+}
+
+/// Opens the platform view of fights that just started, and keeps the platform in place
+fn start_encounters(
+    mut commands: Commands,
+    fonts: Res<FontAssets>,
+    new_encounters: Query<(Entity, &RaiderGang), Added<Encounter>>,
+    encounters: Query<(), With<Encounter>>,
+    mut platform: Query<&mut RouteOrders, With<MiningPlatform>>,
+) {
+    for (gang_entity, gang) in new_encounters.iter() {
+        spawn_combat_scene(&mut commands, &fonts, gang_entity, &gang.cars);
+    }
+    if !encounters.is_empty() {
+        for mut orders in platform.iter_mut() {
+            if !orders.0.is_empty() {
+                orders.0.clear();
+            }
+        }
+    }
+}
+
+fn fight(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut gangs: Query<
+        (Entity, &mut RaiderGang, &mut Encounter, &mut Wallet),
+        Without<MiningPlatform>,
+    >,
+    mut platform: Query<
+        (
+            &mut Hull,
+            &WeaponMounts,
+            &PlatformModules,
+            &Crew,
+            &mut Inventory,
+            &mut Wallet,
+        ),
+        With<MiningPlatform>,
+    >,
+    mut radio: EventWriter<RadioMessage>,
+) {
+    let Ok((mut hull, mounts, modules, crew, mut inventory, mut wallet)) =
+        platform.get_single_mut()
+    else {
+        return;
+    };
+    let aim = MANUAL_AIM + (1.0 - MANUAL_AIM) * modules.effect(ModuleKind::Turrets);
+    let rested =
+        TIRED_DEFENDERS + (1.0 - TIRED_DEFENDERS) * modules.effect(ModuleKind::LivingQuarters);
+    let defenders = (crew.0 as f32 * rested).round() as u32;
+    for (gang_entity, mut gang, mut encounter, mut gang_wallet) in gangs.iter_mut() {
+        encounter.since_round += time.delta_seconds();
+        while encounter.since_round >= COMBAT_ROUND {
+            encounter.since_round -= COMBAT_ROUND;
+            let Some(outcome) = encounter.fight_round(&mut gang, &mut hull, mounts, aim, defenders)
+            else {
+                continue;
+            };
+            let text = settle(
+                outcome,
+                &encounter,
+                &mut gang,
+                &mut gang_wallet,
+                &mut inventory,
+                &mut wallet,
+            );
+            radio.send(RadioMessage {
+                from: "Deck".to_string(),
+                text,
+            });
+            if outcome == Outcome::RaidersDestroyed {
+                commands.entity(gang_entity).despawn_recursive();
+            } else {
+                commands.entity(gang_entity).remove::<Encounter>();
+            }
+            break;
+        }
+    }
+}
+
+fn update_combat_scene(
+    gangs: Query<(&RaiderGang, &Encounter)>,
+    platform: Query<&Hull, With<MiningPlatform>>,
+    scenes: Query<(&CombatScene, Option<&Children>)>,
+    mut cars: Query<(&CombatCar, &mut Fill)>,
+    mut panels: Query<(&CombatScene, &mut Text), With<CombatPanel>>,
+) {
+    let Ok(hull) = platform.get_single() else {
+        return;
+    };
+    for (scene, children) in scenes.iter() {
+        let Ok((gang, _)) = gangs.get(scene.gang) else {
+            continue;
+        };
+        for child in children.into_iter().flatten() {
+            if let Ok((car, mut fill)) = cars.get_mut(*child) {
+                let color = if gang.cars[car.index].is_destroyed() {
+                    WRECK_COLOR
+                } else {
+                    CAR_COLOR
+                };
+                if fill.color != color {
+                    fill.color = color;
+                }
+            }
+        }
+    }
+    for (scene, mut text) in panels.iter_mut() {
+        let Ok((gang, encounter)) = gangs.get(scene.gang) else {
+            continue;
+        };
+        let cars: Vec<String> = gang
+            .cars
+            .iter()
+            .map(|car| format!("{}/{}", car.hp, car.max_hp))
+            .collect();
+        text.sections[0].value = format!(
+            "Attacked by {}\nHull: {}/{}  Raider cars: {}\nBoarded: {}/{}\n{}",
+            gang.name,
+            hull.hp,
+            hull.max_hp,
+            cars.join(" "),
+            encounter.boardings,
+            BOARDINGS_TO_TAKE,
+            Vec::from(encounter.log.clone()).join("\n")
+        );
+    }
+}
+
+/// Removes the view of fights that are over
+fn despawn_combat_scenes(
+    mut commands: Commands,
+    scenes: Query<(Entity, &CombatScene)>,
+    encounters: Query<(), With<Encounter>>,
+) {
+    for (entity, scene) in scenes.iter() {
+        if encounters.get(scene.gang).is_err() {
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::raiders::generate_gang;
+
+    /// Fights rounds until the fight ends, returns how it ended and the state after it
+    fn fight_to_the_end(
+        mut gang: RaiderGang,
+        mut hull: Hull,
+    ) -> (Outcome, Encounter, RaiderGang, Hull) {
+        let mut encounter = Encounter::default();
+        loop {
+            let outcome =
+                encounter.fight_round(&mut gang, &mut hull, &WeaponMounts::default(), 1.0, 8);
+            if let Some(outcome) = outcome {
+                return (outcome, encounter, gang, hull);
+            }
+            assert!(encounter.round < 1000, "Fight doesn't end");
+        }
+    }
+
+    #[test]
+    fn same_gang_fights_the_same() {
+        let (gang, _) = generate_gang(&mut ChaCha8Rng::seed_from_u64(3));
+        let (outcome, encounter, gang_after, hull_after) =
+            fight_to_the_end(gang.clone(), Hull::default());
+        let (outcome_again, encounter_again, gang_again, hull_again) =
+            fight_to_the_end(gang, Hull::default());
+        assert_eq!(outcome, outcome_again);
+        assert_eq!(encounter.round, encounter_again.round);
+        assert_eq!(encounter.boardings, encounter_again.boardings);
+        assert_eq!(encounter.salvage, encounter_again.salvage);
+        assert_eq!(gang_after, gang_again);
+        assert_eq!(hull_after, hull_again);
+    }
+
+    #[test]
+    fn breached_hull_is_boarded_at_once() {
+        let (gang, _) = generate_gang(&mut ChaCha8Rng::seed_from_u64(3));
+        let hull = Hull { hp: 0, ..default() };
+        let (outcome, encounter, ..) = fight_to_the_end(gang, hull);
+        assert_eq!(outcome, Outcome::PlatformBoarded);
+        assert_eq!(encounter.round, 1);
+    }
+
+    #[test]
+    fn loot_that_does_not_fit_stays_behind() {
+        let (mut gang, _) = generate_gang(&mut ChaCha8Rng::seed_from_u64(3));
+        let encounter = Encounter::default();
+        let mut inventory = Inventory::new(1_000, 1_000);
+
+        let mut gang_wallet = Wallet(500);
+        let mut wallet = Wallet(u32::MAX - 200);
+        settle(
+            Outcome::RaidersDestroyed,
+            &encounter,
+            &mut gang,
+            &mut gang_wallet,
+            &mut inventory,
+            &mut wallet,
+        );
+        assert_eq!(wallet, Wallet(u32::MAX));
+        assert_eq!(gang_wallet, Wallet(300));
+
+        let mut gang_wallet = Wallet(u32::MAX - 100);
+        let mut wallet = Wallet(1_000);
+        settle(
+            Outcome::PlatformBoarded,
+            &encounter,
+            &mut gang,
+            &mut gang_wallet,
+            &mut inventory,
+            &mut wallet,
+        );
+        assert_eq!(gang_wallet, Wallet(u32::MAX));
+        assert_eq!(wallet, Wallet(900));
+    }
+}
@@ -0,0 +1,72 @@
+use std::{env, fs, io::ErrorKind, process};
+
+use super::{world_seed::WorldSeed, WINDOW_HEIGHT};
+use bevy::{prelude::*, window::PresentMode};
+use serde::{Deserialize, Serialize};
+
+const CONFIG_PATH: &str = "config.ron";
+const SEED_ENV_VAR: &str = "MERKHYL_SEED";
+
+/// Game settings, read from `config.ron`. The seed can also be set with `MERKHYL_SEED`
+/// environment variable or `--seed` argument, in order of increasing priority.
+#[derive(Resource, Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub window_height: f32,
+    pub present_mode: PresentMode,
+    /// World seed as 64 hex digits, random if not set. Seeds of worlds from older versions are
+    /// prefixed with `legacy:` or `v1:`.
+    pub seed: Option<String>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            window_height: WINDOW_HEIGHT,
+            present_mode: PresentMode::Fifo,
+            seed: None,
+        }
+    }
+}
+
+impl Config {
+    // Logging is not set up yet at this point, so errors go straight to stderr. Exits when the seed
+    // is missing or invalid rather than playing a world that wasn't asked for.
+    pub fn load() -> Self {
+        let mut config = match fs::read_to_string(CONFIG_PATH) {
+            Ok(contents) => ron::from_str(&contents).unwrap_or_else(|e| {
+                eprintln!("Failed to parse {}, using defaults: {}", CONFIG_PATH, e);
+                Self::default()
+            }),
+            Err(e) if e.kind() == ErrorKind::NotFound => Self::default(),
+            Err(e) => {
+                eprintln!("Failed to read {}, using defaults: {}", CONFIG_PATH, e);
+                Self::default()
+            }
+        };
+        if let Ok(seed) = env::var(SEED_ENV_VAR) {
+            config.seed = Some(seed);
+        }
+        let mut args = env::args().skip(1);
+        while let Some(arg) = args.next() {
+            if arg == "--seed" {
+                let Some(seed) = args.next() else {
+                    eprintln!("Missing value of --seed");
+                    process::exit(2);
+                };
+                config.seed = Some(seed);
+            } else if let Some(seed) = arg.strip_prefix("--seed=") {
+                config.seed = Some(seed.to_string());
+            } else {
+                eprintln!("Unknown argument {:?}", arg);
+            }
+        }
+        if let Some(seed) = &config.seed {
+            if let Err(e) = seed.parse::<WorldSeed>() {
+                eprintln!("Invalid world seed {:?}: {}", seed, e);
+                process::exit(2);
+            }
+        }
+        config
+    }
+}
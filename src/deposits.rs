@@ -0,0 +1,179 @@
+use std::collections::BTreeMap;
+
+use super::{
+    chunk_management::{TILEMAP_GRID_SIZE, TILEMAP_TYPE},
+    world_seed::{SeedPurpose, WorldSeed},
+    ChunkPos, TileKind, TileVisibility,
+};
+use bevy::prelude::*;
+use bevy_ecs_tilemap::prelude::*;
+use bevy_prototype_lyon::prelude::*;
+use rand::prelude::*;
+use serde::{Deserialize, Serialize};
+
+const DEPOSIT_MARKER_Z: f32 = 1.0;
+
+/// Amount of ore a new deposit holds, in units
+const MIN_RICHNESS: u32 = 50;
+const MAX_RICHNESS: u32 = 300;
+
+pub struct DepositsPlugin;
+
+impl Plugin for DepositsPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system(update_deposit_markers);
+    }
+}
+
+/// What can be mined
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum Ore {
+    Iron,
+    Copper,
+    Salt,
+}
+
+impl Ore {
+    fn map_color(self) -> Color {
+        match self {
+            Self::Iron => Color::rgb(0.7, 0.35, 0.2),
+            Self::Copper => Color::rgb(0.2, 0.8, 0.6),
+            Self::Salt => Color::rgb(0.95, 0.95, 0.95),
+        }
+    }
+}
+
+/// Resource spot on a tile
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Deposit {
+    pub ore: Ore,
+    /// Amount of ore the deposit had when found
+    pub richness: u32,
+    /// Amount of ore left, deposit is depleted at 0
+    pub remaining: u32,
+}
+
+impl Deposit {
+    pub fn is_depleted(&self) -> bool {
+        self.remaining == 0
+    }
+}
+
+/// Deposits of a chunk by local tile position
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChunkDeposits(BTreeMap<(u32, u32), Deposit>);
+
+impl ChunkDeposits {
+    pub fn get(&self, pos: TilePos) -> Option<&Deposit> {
+        self.0.get(&(pos.x, pos.y))
+    }
+
+    pub fn get_mut(&mut self, pos: TilePos) -> Option<&mut Deposit> {
+        self.0.get_mut(&(pos.x, pos.y))
+    }
+}
+
+/// Chance of a tile having a deposit, and which ores it can be, by weight
+fn deposit_odds(kind: TileKind) -> (f64, &'static [(Ore, f32)]) {
+    match kind {
+        TileKind::Empty | TileKind::Dunes => (0.01, &[(Ore::Iron, 0.6), (Ore::Copper, 0.4)]),
+        TileKind::RockyPlateau => (0.04, &[(Ore::Iron, 0.5), (Ore::Copper, 0.5)]),
+        TileKind::SaltFlat => (0.03, &[(Ore::Salt, 1.0)]),
+        // Villages already mine their own, and the platform can't stand in canyons or sand seas
+        TileKind::Village | TileKind::DriedCanyon | TileKind::SandSea => (0.0, &[]),
+    }
+}
+
+pub fn generate_deposits(
+    world_seed: &WorldSeed,
+    chunk_pos: ChunkPos,
+    tiles: &[[TileKind; 32]; 32],
+) -> ChunkDeposits {
+    let mut rng = world_seed.chunk_rng(chunk_pos, SeedPurpose::Deposits);
+    let mut deposits = ChunkDeposits::default();
+    for (x, column) in tiles.iter().enumerate() {
+        for (y, kind) in column.iter().enumerate() {
+            // Same amount of numbers is rolled for every tile, so that terrain doesn't shift where
+            // deposits are
+            let roll: f64 = rng.gen();
+            let ore_roll: f32 = rng.gen();
+            let richness = rng.gen_range(MIN_RICHNESS..=MAX_RICHNESS);
+            let (chance, ores) = deposit_odds(*kind);
+            if roll >= chance {
+                continue;
+            }
+            let total_weight: f32 = ores.iter().map(|(_, weight)| weight).sum();
+            let mut ore_roll = ore_roll * total_weight;
+            let ore = ores
+                .iter()
+                .find(|(_, weight)| {
+                    ore_roll -= weight;
+                    ore_roll < 0.0
+                })
+                .unwrap_or(&ores[ores.len() - 1])
+                .0;
+            deposits.0.insert(
+                (x as u32, y as u32),
+                Deposit {
+                    ore,
+                    richness,
+                    remaining: richness,
+                },
+            );
+        }
+    }
+    deposits
+}
+
+/// Entity of the map marker that shows the deposit on this tile
+#[derive(Component)]
+pub struct DepositMarker(Entity);
+
+/// Shows deposits on charted tiles, and removes markers of depleted ones
+fn update_deposit_markers(
+    mut commands: Commands,
+    tiles: Query<
+        (
+            Entity,
+            &Deposit,
+            &TileVisibility,
+            &TilePos,
+            &TilemapId,
+            Option<&DepositMarker>,
+        ),
+        Or<(Changed<Deposit>, Changed<TileVisibility>)>,
+    >,
+) {
+    for (tile, deposit, visibility, tile_pos, tilemap_id, marker) in tiles.iter() {
+        let shown = !(deposit.is_depleted() || matches!(visibility, TileVisibility::Unknown));
+        match (shown, marker) {
+            (true, None) => {
+                let marker = commands
+                    .spawn((
+                        ShapeBundle {
+                            path: GeometryBuilder::build_as(&shapes::RegularPolygon {
+                                sides: 4,
+                                feature: shapes::RegularPolygonFeature::Radius(5.0),
+                                ..default()
+                            }),
+                            transform: Transform::from_translation(
+                                tile_pos
+                                    .center_in_world(&TILEMAP_GRID_SIZE, &TILEMAP_TYPE)
+                                    .extend(DEPOSIT_MARKER_Z),
+                            ),
+                            ..default()
+                        },
+                        Fill::color(deposit.ore.map_color()),
+                    ))
+                    .id();
+                commands.entity(tilemap_id.0).add_child(marker);
+                commands.entity(tile).insert(DepositMarker(marker));
+            }
+            (false, Some(DepositMarker(marker))) => {
+                commands.entity(*marker).despawn_recursive();
+                commands.entity(tile).remove::<DepositMarker>();
+            }
+            _ => (),
+        }
+    }
+}
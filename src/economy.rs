@@ -0,0 +1,114 @@
+use std::{error::Error, fmt};
+
+use super::inventory::{Inventory, InventoryError, Item, Wallet};
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// What a market asks and pays for one unit of an item
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Prices {
+    /// Price the player pays when buying
+    pub buy: u32,
+    /// Price the player gets when selling
+    pub sell: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TradeAction {
+    Buy,
+    Sell,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TradeError {
+    NotTraded(Item),
+    NotEnoughMoney { price: u32, available: u32 },
+    PartnerNotEnoughMoney { price: u32, available: u32 },
+    TooMuchMoney,
+    Inventory(InventoryError),
+}
+
+impl fmt::Display for TradeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NotTraded(item) => write!(f, "{} is not traded here", item.name()),
+            Self::NotEnoughMoney { price, available } => {
+                write!(f, "costs {}, only {} on hand", price, available)
+            }
+            Self::PartnerNotEnoughMoney { price, available } => {
+                write!(f, "worth {}, they only have {}", price, available)
+            }
+            Self::TooMuchMoney => write!(f, "too much money to count"),
+            Self::Inventory(e) => e.fmt(f),
+        }
+    }
+}
+
+impl Error for TradeError {}
+
+impl From<InventoryError> for TradeError {
+    fn from(e: InventoryError) -> Self {
+        Self::Inventory(e)
+    }
+}
+
+/// Price lookup of anyone that trades, used by the trade screen and by traders
+pub trait Market {
+    fn name(&self) -> &str;
+
+    /// Prices of an item given what the market has in stock, `None` if it's not traded there
+    fn prices(&self, item: Item, stock: &Inventory) -> Option<Prices>;
+
+    /// Items that are traded, in a stable order
+    fn goods(&self) -> Vec<Item>;
+}
+
+/// Someone the player can trade with when the platform gets next to them
+#[derive(Component, Debug, Default)]
+pub struct TradePartner {
+    /// Set while the platform is next to the partner
+    pub met_platform: bool,
+    /// Set while the player is trading, partners that move wait meanwhile
+    pub trading: bool,
+}
+
+/// Exchanges `amount` of the item between the player and a market at the market's current
+/// prices, returns the price
+pub fn trade(
+    market: &dyn Market,
+    action: TradeAction,
+    item: Item,
+    amount: u32,
+    player: (&mut Inventory, &mut Wallet),
+    partner: (&mut Inventory, &mut Wallet),
+) -> Result<u32, TradeError> {
+    let prices = market
+        .prices(item, partner.0)
+        .ok_or(TradeError::NotTraded(item))?;
+    let ((from_inventory, from_wallet), (to_inventory, to_wallet)) = match action {
+        TradeAction::Buy => (partner, player),
+        TradeAction::Sell => (player, partner),
+    };
+    let price = match action {
+        TradeAction::Buy => prices.buy,
+        TradeAction::Sell => prices.sell,
+    }
+    .checked_mul(amount)
+    .ok_or(TradeError::TooMuchMoney)?;
+    if to_wallet.0 < price {
+        let available = to_wallet.0;
+        return Err(match action {
+            TradeAction::Buy => TradeError::NotEnoughMoney { price, available },
+            TradeAction::Sell => TradeError::PartnerNotEnoughMoney { price, available },
+        });
+    }
+    // Checked before anything changes hands
+    let received = from_wallet
+        .0
+        .checked_add(price)
+        .ok_or(TradeError::TooMuchMoney)?;
+    from_inventory.transfer(to_inventory, item, amount)?;
+    to_wallet.0 -= price;
+    from_wallet.0 = received;
+    Ok(price)
+}
@@ -0,0 +1,161 @@
+use super::{
+    radio::RadioMessage,
+    ui::text_style,
+    world_seed::{SeedPurpose, WorldSeed},
+    FontAssets,
+};
+use bevy::prelude::*;
+use rand::prelude::*;
+use rand_chacha::ChaCha8Rng;
+use serde::{Deserialize, Serialize};
+
+/// Seconds in a day, the suns go around once
+const DAY_LENGTH: f32 = 1200.0;
+/// Part of the day after which both suns are low and it gets a bit darker
+const DUSK_START: f32 = 0.85;
+/// Seconds between the end of a sandstorm and the start of the next one
+const MIN_STORM_INTERVAL: f32 = 300.0;
+const MAX_STORM_INTERVAL: f32 = 900.0;
+/// Seconds a sandstorm lasts
+const MIN_STORM_LENGTH: f32 = 60.0;
+const MAX_STORM_LENGTH: f32 = 180.0;
+
+pub struct EnvironmentPlugin;
+
+impl Plugin for EnvironmentPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<TimeOfDay>()
+            .init_resource::<Sandstorms>()
+            .add_startup_system(spawn_clock)
+            .add_system(advance_time)
+            .add_system(run_sandstorms)
+            .add_system(update_clock.after(advance_time).after(run_sandstorms));
+    }
+}
+
+/// Time of the day, counted from the moment the convoy found the platform
+#[derive(Resource, Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct TimeOfDay {
+    pub day: u32,
+    /// Seconds since the start of the day
+    pub seconds: f32,
+}
+
+impl TimeOfDay {
+    /// Whether it's the darker time of the day, when both suns are low
+    pub fn is_dusk(&self) -> bool {
+        self.seconds >= DAY_LENGTH * DUSK_START
+    }
+
+    /// Hours and minutes on a 24 hour clock
+    fn clock(&self) -> (u32, u32) {
+        let minutes = (self.seconds / DAY_LENGTH * 24.0 * 60.0) as u32;
+        (minutes / 60, minutes % 60)
+    }
+}
+
+/// Sandstorms come one after another, each gets its own random stream
+#[derive(Resource, Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Sandstorms {
+    storms: u64,
+    /// Seconds until the next storm
+    next_storm: f32,
+    /// Seconds until the current storm ends, zero when there is none
+    remaining: f32,
+}
+
+impl Default for Sandstorms {
+    fn default() -> Self {
+        Self {
+            storms: 0,
+            next_storm: MAX_STORM_INTERVAL,
+            remaining: 0.0,
+        }
+    }
+}
+
+impl Sandstorms {
+    pub fn is_active(&self) -> bool {
+        self.remaining > 0.0
+    }
+}
+
+/// Text with the time and weather
+#[derive(Component)]
+struct Clock;
+
+fn spawn_clock(mut commands: Commands, fonts: Res<FontAssets>) {
+    commands.spawn((
+        Clock,
+        TextBundle::from_section("", text_style(&fonts)).with_style(Style {
+            position_type: PositionType::Absolute,
+            position: UiRect {
+                left: Val::Percent(45.0),
+                top: Val::Px(10.0),
+                ..default()
+            },
+            ..default()
+        }),
+    ));
+}
+
+fn advance_time(time: Res<Time>, mut time_of_day: ResMut<TimeOfDay>) {
+    time_of_day.seconds += time.delta_seconds();
+    while time_of_day.seconds >= DAY_LENGTH {
+        time_of_day.seconds -= DAY_LENGTH;
+        time_of_day.day += 1;
+    }
+}
+
+fn run_sandstorms(
+    time: Res<Time>,
+    world_seed: Res<WorldSeed>,
+    mut sandstorms: ResMut<Sandstorms>,
+    mut radio: EventWriter<RadioMessage>,
+) {
+    let seconds = time.delta_seconds();
+    if sandstorms.is_active() {
+        sandstorms.remaining -= seconds;
+        if !sandstorms.is_active() {
+            sandstorms.remaining = 0.0;
+            radio.send(RadioMessage {
+                from: "Lookout".to_string(),
+                text: "The storm is over, we can see again.".to_string(),
+            });
+        }
+        return;
+    }
+    sandstorms.next_storm -= seconds;
+    if sandstorms.next_storm > 0.0 {
+        return;
+    }
+    let mut rng = ChaCha8Rng::from_seed(world_seed.world_seed(SeedPurpose::Sandstorms));
+    rng.set_stream(sandstorms.storms);
+    sandstorms.storms += 1;
+    sandstorms.remaining = rng.gen_range(MIN_STORM_LENGTH..=MAX_STORM_LENGTH);
+    sandstorms.next_storm = rng.gen_range(MIN_STORM_INTERVAL..=MAX_STORM_INTERVAL);
+    radio.send(RadioMessage {
+        from: "Lookout".to_string(),
+        text: "Sandstorm! Can't see a thing out there.".to_string(),
+    });
+}
+
+fn update_clock(
+    time_of_day: Res<TimeOfDay>,
+    sandstorms: Res<Sandstorms>,
+    mut clock: Query<&mut Text, With<Clock>>,
+) {
+    let (hours, minutes) = time_of_day.clock();
+    let mut text = format!("Day {} {:02}:{:02}", time_of_day.day + 1, hours, minutes);
+    if time_of_day.is_dusk() {
+        text.push_str(" dusk");
+    }
+    if sandstorms.is_active() {
+        text.push_str(" sandstorm");
+    }
+    for mut clock_text in clock.iter_mut() {
+        if clock_text.sections[0].value != text {
+            clock_text.sections[0].value = text.clone();
+        }
+    }
+}
@@ -0,0 +1,241 @@
+use std::{collections::BTreeMap, error::Error, fmt};
+
+use super::deposits::Ore;
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// Anything that can be carried in an inventory
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum Item {
+    Ore(Ore),
+    Scrap,
+    /// Liters of fuel
+    Fuel,
+    /// Liters of water
+    Water,
+    SpareParts,
+}
+
+impl Item {
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Ore(Ore::Iron) => "iron ore",
+            Self::Ore(Ore::Copper) => "copper ore",
+            Self::Ore(Ore::Salt) => "salt",
+            Self::Scrap => "scrap",
+            Self::Fuel => "fuel",
+            Self::Water => "water",
+            Self::SpareParts => "spare parts",
+        }
+    }
+
+    /// Mass of one unit, in kilograms
+    pub fn mass(self) -> u32 {
+        match self {
+            Self::Ore(Ore::Iron) => 50,
+            Self::Ore(Ore::Copper) => 45,
+            Self::Ore(Ore::Salt) => 20,
+            Self::Scrap => 30,
+            Self::Fuel => 1,
+            Self::Water => 1,
+            Self::SpareParts => 25,
+        }
+    }
+
+    /// Volume of one unit, in liters
+    pub fn volume(self) -> u32 {
+        match self {
+            Self::Ore(_) => 10,
+            Self::Scrap => 40,
+            Self::Fuel => 1,
+            Self::Water => 1,
+            Self::SpareParts => 20,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InventoryError {
+    /// Adding would go over the mass limit by this many kilograms
+    TooHeavy(u32),
+    /// Adding would go over the volume limit by this many liters
+    TooBulky(u32),
+    /// There is less of the item than asked for
+    NotEnough { item: Item, available: u32 },
+    /// Total mass or volume would be too large to count
+    Overflow,
+}
+
+impl fmt::Display for InventoryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::TooHeavy(excess) => write!(f, "{} kg over the mass limit", excess),
+            Self::TooBulky(excess) => write!(f, "{} l over the volume limit", excess),
+            Self::NotEnough { item, available } => {
+                write!(f, "only {} of {:?} available", available, item)
+            }
+            Self::Overflow => write!(f, "too much to count"),
+        }
+    }
+}
+
+impl Error for InventoryError {}
+
+/// Money on hand, separate from the items since it weighs next to nothing
+#[derive(Component, Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Wallet(pub u32);
+
+/// Stacks of items with limits on total mass and volume
+#[derive(Component, Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Inventory {
+    items: BTreeMap<Item, u32>,
+    /// In kilograms
+    max_mass: u32,
+    /// In liters
+    max_volume: u32,
+}
+
+impl Inventory {
+    pub fn new(max_mass: u32, max_volume: u32) -> Self {
+        Self {
+            items: BTreeMap::new(),
+            max_mass,
+            max_volume,
+        }
+    }
+
+    /// Inventory that starts with the given items, as long as they fit
+    pub fn with_items(
+        max_mass: u32,
+        max_volume: u32,
+        items: &[(Item, u32)],
+    ) -> Result<Self, InventoryError> {
+        let mut inventory = Self::new(max_mass, max_volume);
+        for (item, amount) in items {
+            inventory.add(*item, *amount)?;
+        }
+        Ok(inventory)
+    }
+
+    /// In kilograms
+    pub fn max_mass(&self) -> u32 {
+        self.max_mass
+    }
+
+    /// In liters
+    pub fn max_volume(&self) -> u32 {
+        self.max_volume
+    }
+
+    pub fn count(&self, item: Item) -> u32 {
+        self.items.get(&item).copied().unwrap_or(0)
+    }
+
+    /// Items with their amounts, in a stable order
+    pub fn items(&self) -> impl Iterator<Item = (Item, u32)> + '_ {
+        self.items.iter().map(|(item, amount)| (*item, *amount))
+    }
+
+    /// Sum of a measure of one unit over all items, `None` when it doesn't fit in `u32`
+    fn total(&self, per_unit: impl Fn(Item) -> u32) -> Option<u32> {
+        self.items().try_fold(0u32, |total, (item, amount)| {
+            per_unit(item).checked_mul(amount)?.checked_add(total)
+        })
+    }
+
+    /// Total mass, in kilograms. Saturates, which is over any limit.
+    pub fn mass(&self) -> u32 {
+        self.total(Item::mass).unwrap_or(u32::MAX)
+    }
+
+    /// Total volume, in liters. Saturates, which is over any limit.
+    pub fn volume(&self) -> u32 {
+        self.total(Item::volume).unwrap_or(u32::MAX)
+    }
+
+    /// How many units of the item can still be added
+    pub fn space_for(&self, item: Item) -> u32 {
+        let by_mass = (self.max_mass.saturating_sub(self.mass())) / item.mass();
+        let by_volume = (self.max_volume.saturating_sub(self.volume())) / item.volume();
+        by_mass.min(by_volume)
+    }
+
+    /// Checks that `amount` of the item fits, without adding it
+    pub fn check_add(&self, item: Item, amount: u32) -> Result<(), InventoryError> {
+        let mass = self
+            .total(Item::mass)
+            .zip(item.mass().checked_mul(amount))
+            .and_then(|(mass, added)| mass.checked_add(added))
+            .ok_or(InventoryError::Overflow)?;
+        if mass > self.max_mass {
+            return Err(InventoryError::TooHeavy(mass - self.max_mass));
+        }
+        let volume = self
+            .total(Item::volume)
+            .zip(item.volume().checked_mul(amount))
+            .and_then(|(volume, added)| volume.checked_add(added))
+            .ok_or(InventoryError::Overflow)?;
+        if volume > self.max_volume {
+            return Err(InventoryError::TooBulky(volume - self.max_volume));
+        }
+        Ok(())
+    }
+
+    /// Adds all of `amount` or nothing
+    pub fn add(&mut self, item: Item, amount: u32) -> Result<(), InventoryError> {
+        self.check_add(item, amount)?;
+        if amount > 0 {
+            *self.items.entry(item).or_default() += amount;
+        }
+        Ok(())
+    }
+
+    /// Removes all of `amount` or nothing
+    pub fn remove(&mut self, item: Item, amount: u32) -> Result<(), InventoryError> {
+        let available = self.count(item);
+        if available < amount {
+            return Err(InventoryError::NotEnough { item, available });
+        }
+        if available == amount {
+            self.items.remove(&item);
+        } else {
+            self.items.insert(item, available - amount);
+        }
+        Ok(())
+    }
+
+    /// Moves all of `amount` to another inventory or nothing
+    pub fn transfer(
+        &mut self,
+        to: &mut Inventory,
+        item: Item,
+        amount: u32,
+    ) -> Result<(), InventoryError> {
+        let available = self.count(item);
+        if available < amount {
+            return Err(InventoryError::NotEnough { item, available });
+        }
+        to.add(item, amount)?;
+        self.remove(item, amount)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn overflow_is_an_error() {
+        let mut inventory = Inventory::new(u32::MAX, u32::MAX);
+        assert_eq!(
+            inventory.add(Item::Scrap, u32::MAX),
+            Err(InventoryError::Overflow)
+        );
+        inventory.add(Item::Fuel, u32::MAX - 10).unwrap();
+        assert_eq!(
+            inventory.add(Item::Water, 20),
+            Err(InventoryError::Overflow)
+        );
+        assert_eq!(inventory.space_for(Item::Water), 10);
+    }
+}
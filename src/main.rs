@@ -5,17 +5,61 @@ use bevy::{
     prelude::*,
     render::camera::ScalingMode,
     sprite::Anchor,
-    window::{PresentMode, WindowResolution},
+    window::WindowResolution,
 };
 use bevy_ecs_tilemap::{
-    helpers::hex_grid::neighbors::HexRowDirection,
+    helpers::hex_grid::neighbors::{HexDirection, HexRowDirection},
     prelude::{offset::RowEvenPos, *},
 };
 use bevy_prototype_lyon::prelude::*;
-use chunk_management::{global_from_chunk_and_local, ChunkManagementPlugin};
-use rand::prelude::*;
-
+use charting::{ChartingPlugin, Lookouts};
+use chunk_management::{ChunkLoader, ChunkManagementPlugin};
+use combat::{CombatPlugin, Hull, WeaponMounts};
+use config::Config;
+use deposits::DepositsPlugin;
+use environment::EnvironmentPlugin;
+use inventory::{Inventory, Item, Wallet};
+use map_markers::MapMarkersPlugin;
+use mining::{Mining, MiningPlugin};
+use movement::{MapMovement, MovementPlugin};
+use navigation::{NavigationPlugin, RouteLine};
+use platform_modules::{PlatformModules, PlatformModulesPlugin};
+use radio::RadioPlugin;
+use raiders::RaidersPlugin;
+use save::SavePlugin;
+use serde::{Deserialize, Serialize};
+use splines::{Interpolation, Key, Spline};
+use std::f32::consts::{PI, TAU};
+use terrain::{TerrainMap, TerrainProperties};
+use trade_screen::TradeScreenPlugin;
+use traders::TradersPlugin;
+use villages::VillagesPlugin;
+use world_seed::WorldSeed;
+
+mod charting;
 mod chunk_management;
+mod combat;
+mod config;
+mod deposits;
+mod economy;
+mod environment;
+mod inventory;
+mod map_markers;
+mod mining;
+mod movement;
+mod navigation;
+mod platform_modules;
+mod radio;
+mod raiders;
+mod save;
+mod terrain;
+#[cfg(test)]
+mod test_support;
+mod trade_screen;
+mod traders;
+mod ui;
+mod villages;
+mod world_seed;
 
 use chunk_management::TILEMAP_GRID_SIZE;
 
@@ -31,19 +75,101 @@ const MAP_TILEMAP_Z: f32 = 900.0;
 const MAP_VIEW_SCALE: f32 = 30.0;
 const PLATFORM_VIEW_SCALE: f32 = 25.0;
 
+/// How long before tile center map markers start turning, in tiles
+const MARKER_TURN_PROGRESS: f32 = 0.25;
+
+/// Tiles per second
+const PLATFORM_MAP_SPEED: f32 = 0.5;
+
+/// Money the convoy had when finding the platform
+const PLATFORM_STARTING_MONEY: u32 = 200;
+/// People of the convoy that settled in the platform
+const PLATFORM_CREW: u32 = 8;
+/// Crew members watching the horizon when the platform sets out
+const PLATFORM_LOOKOUTS: u32 = 1;
+
+/// Cargo hold limits of the platform, in kilograms and liters
+const PLATFORM_MAX_CARGO_MASS: u32 = 20_000;
+const PLATFORM_MAX_CARGO_VOLUME: u32 = 12_000;
+
+// Pathfinding costs
+const MOVE_COST: u32 = 10;
+const REVERSE_MOVE_COST: u32 = 15;
+const TURN_COST: u32 = 5;
+const REVERSE_FLIP_COST: u32 = 30;
+/// Pathfinding cost of one unit of fuel
+const FUEL_COST: u32 = 2;
+
+/// Direction rotated by `steps` sixths of a turn, counter-clockwise
 #[inline]
-fn direction_to_rotation(direction: HexRowDirection) -> Quat {
-    Quat::from_rotation_z(
-        match direction {
-            HexRowDirection::North => 0_f32,
-            HexRowDirection::NorthEast => -30_f32,
-            HexRowDirection::NorthWest => 30_f32,
-            HexRowDirection::South => -180_f32,
-            HexRowDirection::SouthWest => 150_f32,
-            HexRowDirection::SouthEast => 210_f32,
+fn rotate_direction(direction: HexRowDirection, steps: i32) -> HexRowDirection {
+    (HexDirection::from(direction) + steps).into()
+}
+
+/// Tile that is next to `pos` in the given direction
+#[inline]
+fn neighbour(pos: RowEvenPos, direction: HexRowDirection) -> RowEvenPos {
+    pos.offset(direction.into())
+}
+
+/// Angle of a direction on the map, counter-clockwise from up
+#[inline]
+fn direction_to_angle(direction: HexRowDirection) -> f32 {
+    let origin = RowEvenPos { q: 0, r: 0 };
+    let offset = neighbour(origin, direction).center_in_world(&TILEMAP_GRID_SIZE)
+        - origin.center_in_world(&TILEMAP_GRID_SIZE);
+    Vec2::Y.angle_between(offset)
+}
+
+/// Position in world space, moved from tile center towards the tile edge according to progress
+fn map_pos_in_world(map_pos: &MapPos) -> Vec2 {
+    let center = map_pos.pos.center_in_world(&TILEMAP_GRID_SIZE);
+    let forward = neighbour(map_pos.pos, map_pos.current_direction)
+        .center_in_world(&TILEMAP_GRID_SIZE)
+        - center;
+    center + forward * (map_pos.progress - 0.5)
+}
+
+/// Rotation in world space, easing towards target direction while approaching tile center
+fn map_pos_rotation(map_pos: &MapPos) -> Quat {
+    let current_angle = direction_to_angle(map_pos.current_direction);
+    let before_center = if map_pos.reverse {
+        map_pos.progress > 0.5
+    } else {
+        map_pos.progress < 0.5
+    };
+    let angle = match map_pos.target_direction {
+        Some(target) if before_center => {
+            // Shortest way around
+            let target_angle = current_angle
+                + (direction_to_angle(target) - current_angle + PI).rem_euclid(TAU)
+                - PI;
+            let keys = if map_pos.reverse {
+                vec![
+                    Key::new(0.5, target_angle, Interpolation::Cosine),
+                    Key::new(
+                        0.5 + MARKER_TURN_PROGRESS,
+                        current_angle,
+                        Interpolation::Cosine,
+                    ),
+                ]
+            } else {
+                vec![
+                    Key::new(
+                        0.5 - MARKER_TURN_PROGRESS,
+                        current_angle,
+                        Interpolation::Cosine,
+                    ),
+                    Key::new(0.5, target_angle, Interpolation::Cosine),
+                ]
+            };
+            Spline::from_vec(keys)
+                .clamped_sample(map_pos.progress)
+                .unwrap_or(current_angle)
         }
-        .to_radians(),
-    )
+        _ => current_angle,
+    };
+    Quat::from_rotation_z(angle)
 }
 
 type ChunkPos = IVec2;
@@ -60,11 +186,23 @@ enum TileVisibility {
 }
 
 /// What kind of tile it is
-#[derive(Component, Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 #[repr(u8)]
 enum TileKind {
     Empty = 1,
     Village = 2,
+    Dunes = 3,
+    RockyPlateau = 4,
+    SaltFlat = 5,
+    DriedCanyon = 6,
+    SandSea = 7,
+}
+
+impl TileKind {
+    /// Whether villages can be found on this kind of terrain
+    fn is_habitable(self) -> bool {
+        matches!(self, Self::Empty | Self::Dunes | Self::SaltFlat)
+    }
 }
 
 /// Marker struct for chunks
@@ -73,22 +211,12 @@ struct Chunk {
     pos: ChunkPos,
 }
 
-#[derive(Resource)]
-struct WorldSeed {
-    seed: [u8; 32],
-}
-
-impl Default for WorldSeed {
-    fn default() -> Self {
-        let mut seed: [u8; 32] = thread_rng().gen();
-        seed[(32 - 8)..].copy_from_slice(&[0; 8]);
-        info!("World seed is {:02X?}", seed);
-        WorldSeed { seed }
-    }
-}
-
 /// Position on a map, with track of how much progress is made through the map tile and what the
-/// next tile should be
+/// next tile should be.
+///
+/// Progress goes from 0.0 to 1.0 along the current direction: 0.0 is the edge the tile was
+/// entered from, 0.5 is the center, and 1.0 is the edge it will be left through. When reversing,
+/// progress goes down instead.
 #[derive(Component, Debug, Clone, PartialEq)]
 struct MapPos {
     pos: RowEvenPos,
@@ -111,7 +239,7 @@ impl Default for MapPos {
 }
 
 /// Specifies how something can move on a map
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 enum MovementConstraints {
     /// No limitations, can go to any neighbouring tile, ignores reverse
     Free,
@@ -120,16 +248,80 @@ enum MovementConstraints {
 }
 
 /// Used for pathfinding
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 struct PathfindingPos {
     pos: RowEvenPos,
     direction: HexRowDirection,
     reverse: bool,
 }
 
+/// Cost of entering a tile, slower terrain takes longer to cross
+fn terrain_cost(base_cost: u32, terrain: TerrainProperties) -> u32 {
+    (base_cost as f32 / terrain.speed).round() as u32 + terrain.fuel_cost * FUEL_COST
+}
+
 impl PathfindingPos {
-    fn successors(&self, constraints: MovementConstraints) -> Vec<(Self, u32)> {
-        todo!()
+    fn successors(
+        &self,
+        constraints: MovementConstraints,
+        terrain: &TerrainMap,
+    ) -> Vec<(Self, u32)> {
+        match constraints {
+            MovementConstraints::Free => (0..6)
+                .filter_map(|steps| {
+                    let direction = rotate_direction(self.direction, steps);
+                    let pos = Self {
+                        pos: neighbour(self.pos, direction),
+                        direction,
+                        reverse: false,
+                    };
+                    let properties = terrain.properties_at(pos.pos);
+                    properties
+                        .is_passable(constraints)
+                        .then(|| (pos, terrain_cost(MOVE_COST, properties)))
+                })
+                .collect(),
+            MovementConstraints::Platform => {
+                // Direction the platform is travelling in, facing stays the same when reversing
+                let travel_direction = if self.reverse {
+                    rotate_direction(self.direction, 3)
+                } else {
+                    self.direction
+                };
+                let mut successors: Vec<(Self, u32)> = [0, 1, -1]
+                    .into_iter()
+                    .filter_map(|steps| {
+                        let pos = Self {
+                            pos: neighbour(self.pos, rotate_direction(travel_direction, steps)),
+                            direction: rotate_direction(self.direction, steps),
+                            reverse: self.reverse,
+                        };
+                        let properties = terrain.properties_at(pos.pos);
+                        if !properties.is_passable(constraints) {
+                            return None;
+                        }
+                        let mut cost = if self.reverse {
+                            REVERSE_MOVE_COST
+                        } else {
+                            MOVE_COST
+                        };
+                        cost = terrain_cost(cost, properties);
+                        if steps != 0 {
+                            cost += TURN_COST
+                        }
+                        Some((pos, cost))
+                    })
+                    .collect();
+                successors.push((
+                    Self {
+                        reverse: !self.reverse,
+                        ..self.clone()
+                    },
+                    REVERSE_FLIP_COST,
+                ));
+                successors
+            }
+        }
     }
 }
 
@@ -154,6 +346,22 @@ impl FromWorld for SpriteAssets {
     }
 }
 
+/// Fonts used in the game
+#[derive(Resource)]
+struct FontAssets {
+    /// Font for text on screen
+    ui: Handle<Font>,
+}
+
+impl FromWorld for FontAssets {
+    fn from_world(world: &mut World) -> Self {
+        let asset_server = world.get_resource::<AssetServer>().unwrap();
+        Self {
+            ui: asset_server.load("fonts/DejaVuSansMono.ttf"),
+        }
+    }
+}
+
 /// Current view mode, map is hiddent when viewing the world
 #[derive(Resource)]
 enum CurrentView {
@@ -183,11 +391,24 @@ struct PlayerVehicle;
 #[derive(Component)]
 struct Npc;
 
-#[derive(Component)]
-struct PlayerMapMarker;
+/// People on board of a vehicle, they fight off anyone trying to get in
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+struct Crew(u32);
 
-#[derive(Component)]
-struct PlayerDirectionMapMarker;
+impl Default for Crew {
+    /// Who came along with the convoy
+    fn default() -> Self {
+        Self(PLATFORM_CREW)
+    }
+}
+
+/// Who something belongs to, decides how it's shown on the map
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+enum Faction {
+    Player,
+    Neutral,
+    Hostile,
+}
 
 fn spawn_camera(mut commands: Commands) {
     let mut camera = Camera2dBundle::default();
@@ -233,7 +454,22 @@ fn spawn_camera(mut commands: Commands) {
         });
 }
 
+/// Cargo hold of the platform with supplies the convoy brought along
+fn platform_inventory() -> Inventory {
+    Inventory::with_items(
+        PLATFORM_MAX_CARGO_MASS,
+        PLATFORM_MAX_CARGO_VOLUME,
+        &[
+            (Item::Fuel, 500),
+            (Item::Water, 200),
+            (Item::SpareParts, 10),
+        ],
+    )
+    .expect("Starting supplies don't fit in the platform")
+}
+
 fn spawn_platform(mut commands: Commands, sprite: Res<SpriteAssets>) {
+    let modules = PlatformModules::default();
     commands.spawn((
         SpriteBundle {
             texture: sprite.mining_platform.clone(),
@@ -245,9 +481,29 @@ fn spawn_platform(mut commands: Commands, sprite: Res<SpriteAssets>) {
             ..default()
         },
         MapPos::default(),
+        MapMovement {
+            speed: modules.map_speed(),
+            constraints: MovementConstraints::Platform,
+            halt: true,
+        },
         MiningPlatform,
-        PlayerVehicle,
-        ChartRange(5),
+        Mining::default(),
+        platform_inventory(),
+        Wallet(PLATFORM_STARTING_MONEY),
+        Hull::default(),
+        WeaponMounts::default(),
+        Crew(PLATFORM_CREW),
+        Lookouts(PLATFORM_LOOKOUTS),
+        // Nested, the bundle is at the most components a tuple can have
+        (PlayerVehicle, ChunkLoader::PLAYER),
+        Faction::Player,
+        ChartRange(charting::chart_range(
+            modules.radar_range(),
+            PLATFORM_LOOKOUTS,
+            false,
+            false,
+        )),
+        modules,
     ));
     // For visualizing vehicle center on the ground level
     /*
@@ -264,28 +520,15 @@ fn spawn_platform(mut commands: Commands, sprite: Res<SpriteAssets>) {
 }
 
 fn spawn_map(mut commands: Commands) {
-    let TransformBundle {
-        local: transform,
-        global: global_transform,
-    } = TransformBundle::from_transform(Transform::from_xyz(0.0, 0.0, 10.0).with_scale(Vec3 {
-        x: 0.5,
-        y: 1.0,
-        z: 1.0,
-    }));
-    let player_marker = commands
+    let route_line = commands
         .spawn((
-            PlayerMapMarker,
+            RouteLine,
             ShapeBundle {
-                path: GeometryBuilder::build_as(&shapes::RegularPolygon {
-                    sides: 3,
-                    feature: shapes::RegularPolygonFeature::Radius(8.0),
-                    ..default()
-                }),
-                transform,
-                global_transform,
+                path: PathBuilder::new().build(),
+                transform: Transform::from_xyz(0.0, 0.0, 5.0),
                 ..default()
             },
-            Fill::color(Color::rgb(0.0, 1.0, 0.0)),
+            Stroke::new(Color::rgb(1.0, 0.8, 0.0), 2.0),
         ))
         .id();
 
@@ -299,45 +542,7 @@ fn spawn_map(mut commands: Commands) {
             },
         ))
         //.push_children(&chunks)
-        .add_child(player_marker);
-}
-
-fn chart_map(
-    player: Query<(&MapPos, &ChartRange), With<PlayerVehicle>>,
-    mut tiles: Query<(&mut TileVisibility, &TilePos, &TilemapId)>,
-    chunks: Query<&Chunk>,
-) {
-    let (player_pos, chart_range) = player.single();
-    let tiles_in_chart_range: Vec<RowEvenPos> =
-        generate_hexagon(player_pos.pos.into(), chart_range.0)
-            .into_iter()
-            .map(Into::into)
-            .collect();
-    for (mut tile_vis, tile_pos, tilemap_id) in tiles.iter_mut() {
-        let chunk = chunks.get(tilemap_id.0).unwrap();
-        let global_tile_pos = global_from_chunk_and_local(chunk.pos, *tile_pos);
-        if tiles_in_chart_range.contains(&global_tile_pos) {
-            *tile_vis = TileVisibility::Visible
-        } else if matches!(*tile_vis, TileVisibility::Visible) {
-            *tile_vis = TileVisibility::Charted
-        }
-    }
-}
-
-// Breaks when multiple player vehicles: Does not update. Add a entity id of a player vehicle to
-// each marker?
-fn update_marker(
-    mut marker: Query<&mut Transform, With<PlayerMapMarker>>,
-    player: Query<&MapPos, (With<PlayerVehicle>, Changed<MapPos>)>,
-) {
-    let mut marker_transform = marker.single_mut();
-    if let Ok(player_pos) = player.get_single() {
-        marker_transform.translation = player_pos
-            .pos
-            .center_in_world(&TILEMAP_GRID_SIZE)
-            .extend(marker_transform.translation.z);
-        marker_transform.rotation = direction_to_rotation(player_pos.current_direction);
-    }
+        .add_child(route_line);
 }
 
 fn update_map_tiles_texture(
@@ -392,9 +597,15 @@ fn camera_movement(
 }
 
 fn switch_view(
+    mut commands: Commands,
     input: Res<Input<KeyCode>>,
     mut camera: Query<
-        (&mut OrthographicProjection, &mut Transform, &mut Visibility),
+        (
+            Entity,
+            &mut OrthographicProjection,
+            &mut Transform,
+            &mut Visibility,
+        ),
         With<Camera2d>,
     >,
     mut map: Query<&mut Visibility, (With<Map>, Without<Camera2d>)>,
@@ -405,15 +616,20 @@ fn switch_view(
         match *current_view {
             CurrentView::Map => {
                 *map.single_mut() = Visibility::Visible;
-                let (mut projection, mut cam_transform, mut cam_visibility) = camera.single_mut();
+                let (cam_entity, mut projection, mut cam_transform, mut cam_visibility) =
+                    camera.single_mut();
                 *cam_visibility = Visibility::Visible;
                 projection.scale = MAP_VIEW_SCALE;
                 cam_transform.translation = Vec2::new(0.0, 0.0).extend(cam_transform.translation.z);
+                // Panning the map shows what was charted beyond the vehicles
+                commands.entity(cam_entity).insert(ChunkLoader::MAP_CAMERA);
             }
             CurrentView::Platform => {
                 *map.single_mut() = Visibility::Hidden;
-                let (mut projection, mut cam_transform, mut cam_visibility) = camera.single_mut();
+                let (cam_entity, mut projection, mut cam_transform, mut cam_visibility) =
+                    camera.single_mut();
                 *cam_visibility = Visibility::Hidden;
+                commands.entity(cam_entity).remove::<ChunkLoader>();
                 projection.scale = PLATFORM_VIEW_SCALE;
                 cam_transform.translation = Vec2::new(0.0, 6.0).extend(cam_transform.translation.z);
             }
@@ -422,6 +638,7 @@ fn switch_view(
 }
 
 fn main() {
+    let config = Config::load();
     App::new()
         .insert_resource(ClearColor(CLEAR_COLOR))
         .insert_resource(CurrentView::Platform)
@@ -430,10 +647,10 @@ fn main() {
                 .set(WindowPlugin {
                     primary_window: Some(Window {
                         title: "Sun never sets on the sands of Merkhyl".to_string(),
-                        present_mode: PresentMode::Fifo,
+                        present_mode: config.present_mode,
                         resolution: WindowResolution::new(
-                            WINDOW_HEIGHT * ASPECT_RATIO,
-                            WINDOW_HEIGHT,
+                            config.window_height * ASPECT_RATIO,
+                            config.window_height,
                         ),
                         resizable: false,
                         ..default()
@@ -445,7 +662,24 @@ fn main() {
         .add_plugin(ShapePlugin) // bevy_prototype_lyon
         .add_plugin(TilemapPlugin)
         .add_plugin(ChunkManagementPlugin)
+        .add_plugin(MovementPlugin)
+        .add_plugin(NavigationPlugin)
+        .add_plugin(MapMarkersPlugin)
+        .add_plugin(DepositsPlugin)
+        .add_plugin(MiningPlugin)
+        .add_plugin(PlatformModulesPlugin)
+        .add_plugin(EnvironmentPlugin)
+        .add_plugin(ChartingPlugin)
+        .add_plugin(RadioPlugin)
+        .add_plugin(TradersPlugin)
+        .add_plugin(VillagesPlugin)
+        .add_plugin(TradeScreenPlugin)
+        .add_plugin(RaidersPlugin)
+        .add_plugin(CombatPlugin)
+        .add_plugin(SavePlugin)
+        .insert_resource(config)
         .init_resource::<SpriteAssets>()
+        .init_resource::<FontAssets>()
         .init_resource::<WorldSeed>()
         .add_startup_system(spawn_platform)
         .add_startup_system(spawn_camera)
@@ -453,7 +687,93 @@ fn main() {
         .add_system(camera_movement)
         .add_system(switch_view)
         .add_system(update_map_tiles_texture)
-        .add_system(update_marker)
-        .add_system(chart_map)
         .run();
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::test_world;
+
+    fn directions() -> Vec<HexRowDirection> {
+        (0..6)
+            .map(|steps| rotate_direction(MapPos::default().current_direction, steps))
+            .collect()
+    }
+
+    #[test]
+    fn platform_never_turns_sharper_than_one_side() {
+        let world = test_world(1);
+        let terrain = world.terrain();
+        for direction in directions() {
+            for reverse in [false, true] {
+                let from = PathfindingPos {
+                    pos: RowEvenPos { q: 5, r: 6 },
+                    direction,
+                    reverse,
+                };
+                let travel_direction = if reverse {
+                    rotate_direction(direction, 3)
+                } else {
+                    direction
+                };
+                let successors = from.successors(MovementConstraints::Platform, &terrain);
+                // Forward, both sides and switching between forward and reverse
+                assert_eq!(successors.len(), 4);
+                for (next, _) in successors {
+                    if next.pos == from.pos {
+                        assert_eq!(next.direction, direction);
+                        assert_eq!(next.reverse, !reverse);
+                        continue;
+                    }
+                    assert_eq!(next.reverse, reverse);
+                    let steps = [0, 1, -1]
+                        .into_iter()
+                        .find(|steps| next.direction == rotate_direction(direction, *steps))
+                        .expect("Turned more than one side");
+                    assert_eq!(
+                        next.pos,
+                        neighbour(from.pos, rotate_direction(travel_direction, steps))
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn free_vehicles_go_anywhere_around() {
+        let world = test_world(1);
+        let terrain = world.terrain();
+        let from = PathfindingPos {
+            pos: RowEvenPos { q: 5, r: 6 },
+            direction: MapPos::default().current_direction,
+            reverse: true,
+        };
+        let successors = from.successors(MovementConstraints::Free, &terrain);
+        assert_eq!(successors.len(), 6);
+        for direction in directions() {
+            assert!(successors.iter().any(|(next, _)| {
+                next.pos == neighbour(from.pos, direction)
+                    && next.direction == direction
+                    && !next.reverse
+            }));
+        }
+    }
+
+    #[test]
+    fn impassable_tiles_are_not_successors() {
+        let mut world = test_world(1);
+        let from = PathfindingPos {
+            pos: RowEvenPos { q: 5, r: 6 },
+            direction: MapPos::default().current_direction,
+            reverse: false,
+        };
+        let ahead = neighbour(from.pos, from.direction);
+        world
+            .generated_chunks
+            .set_kind(ahead, TileKind::DriedCanyon);
+        let terrain = world.terrain();
+        let successors = from.successors(MovementConstraints::Platform, &terrain);
+        assert!(successors.iter().all(|(next, _)| next.pos != ahead));
+    }
+}
@@ -0,0 +1,137 @@
+use super::{
+    chunk_management::find_tile, map_pos_in_world, map_pos_rotation, Chunk, Faction, Map, MapPos,
+    Npc, PlayerVehicle, TileVisibility,
+};
+use bevy::prelude::*;
+use bevy_ecs_tilemap::prelude::*;
+use bevy_prototype_lyon::prelude::*;
+
+const MAP_MARKER_Z: f32 = 10.0;
+
+pub struct MapMarkersPlugin;
+
+impl Plugin for MapMarkersPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system(spawn_map_markers)
+            .add_system(despawn_map_markers.after(spawn_map_markers))
+            .add_system(update_map_markers.after(despawn_map_markers));
+    }
+}
+
+/// Marker on a map that shows where its owner is
+#[derive(Component)]
+pub struct MapMarker {
+    owner: Entity,
+}
+
+/// Entity of the map marker that shows this entity
+#[derive(Component)]
+pub struct MarkedOnMap(Entity);
+
+fn marker_shape(faction: Faction) -> (usize, Color) {
+    match faction {
+        Faction::Player => (3, Color::rgb(0.0, 1.0, 0.0)),
+        Faction::Neutral => (4, Color::rgb(0.8, 0.8, 0.8)),
+        Faction::Hostile => (5, Color::rgb(1.0, 0.2, 0.1)),
+    }
+}
+
+/// Whether the player can see an npc at this position
+fn is_seen(
+    map_pos: &MapPos,
+    chunks: &Query<(&Chunk, &TileStorage)>,
+    tiles: &Query<&TileVisibility>,
+) -> bool {
+    find_tile(chunks, map_pos.pos)
+        .and_then(|tile| tiles.get(tile).ok())
+        .is_some_and(|visibility| matches!(visibility, TileVisibility::Visible))
+}
+
+fn spawn_map_markers(
+    mut commands: Commands,
+    unmarked: Query<
+        (Entity, &MapPos, Option<&Faction>, Option<&PlayerVehicle>),
+        (Or<(With<PlayerVehicle>, With<Npc>)>, Without<MarkedOnMap>),
+    >,
+    chunks: Query<(&Chunk, &TileStorage)>,
+    tiles: Query<&TileVisibility>,
+    map_entity: Query<Entity, With<Map>>,
+) {
+    let map_entity = map_entity.single();
+    for (owner, map_pos, faction, player_vehicle) in unmarked.iter() {
+        let is_player = player_vehicle.is_some();
+        if !(is_player || is_seen(map_pos, &chunks, &tiles)) {
+            continue;
+        }
+        let faction = faction.copied().unwrap_or(if is_player {
+            Faction::Player
+        } else {
+            Faction::Neutral
+        });
+        let (sides, color) = marker_shape(faction);
+        let TransformBundle {
+            local: transform,
+            global: global_transform,
+        } = TransformBundle::from_transform(
+            Transform::from_translation(map_pos_in_world(map_pos).extend(MAP_MARKER_Z))
+                .with_rotation(map_pos_rotation(map_pos))
+                .with_scale(Vec3 {
+                    x: 0.5,
+                    y: 1.0,
+                    z: 1.0,
+                }),
+        );
+        let marker = commands
+            .spawn((
+                MapMarker { owner },
+                ShapeBundle {
+                    path: GeometryBuilder::build_as(&shapes::RegularPolygon {
+                        sides,
+                        feature: shapes::RegularPolygonFeature::Radius(8.0),
+                        ..default()
+                    }),
+                    transform,
+                    global_transform,
+                    ..default()
+                },
+                Fill::color(color),
+            ))
+            .id();
+        commands.entity(map_entity).add_child(marker);
+        commands.entity(owner).insert(MarkedOnMap(marker));
+    }
+}
+
+/// Removes markers of things that are gone or npcs that went out of sight
+fn despawn_map_markers(
+    mut commands: Commands,
+    markers: Query<(Entity, &MapMarker)>,
+    owners: Query<(&MapPos, Option<&PlayerVehicle>)>,
+    chunks: Query<(&Chunk, &TileStorage)>,
+    tiles: Query<&TileVisibility>,
+) {
+    for (marker, MapMarker { owner }) in markers.iter() {
+        let keep = owners.get(*owner).is_ok_and(|(map_pos, player_vehicle)| {
+            player_vehicle.is_some() || is_seen(map_pos, &chunks, &tiles)
+        });
+        if !keep {
+            commands.entity(marker).despawn_recursive();
+            if let Some(mut owner_commands) = commands.get_entity(*owner) {
+                owner_commands.remove::<MarkedOnMap>();
+            }
+        }
+    }
+}
+
+fn update_map_markers(
+    mut markers: Query<&mut Transform, With<MapMarker>>,
+    owners: Query<(&MapPos, &MarkedOnMap), Changed<MapPos>>,
+) {
+    for (map_pos, MarkedOnMap(marker)) in owners.iter() {
+        if let Ok(mut marker_transform) = markers.get_mut(*marker) {
+            marker_transform.translation =
+                map_pos_in_world(map_pos).extend(marker_transform.translation.z);
+            marker_transform.rotation = map_pos_rotation(map_pos);
+        }
+    }
+}
@@ -0,0 +1,157 @@
+use super::{
+    chunk_management::{chunk_and_local_from_global, find_tile, GeneratedChunks},
+    deposits::Deposit,
+    inventory::{Inventory, Item},
+    movement::MapMovement,
+    platform_modules::{ModuleKind, PlatformModules},
+    Chunk, MapPos, MiningPlatform,
+};
+use bevy::prelude::*;
+use bevy_ecs_tilemap::prelude::*;
+
+/// Units of ore per second with the drill in perfect condition
+const MINING_RATE: f32 = 1.0;
+
+pub struct MiningPlugin;
+
+impl Plugin for MiningPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system(toggle_mining)
+            .add_system(mine.after(toggle_mining));
+    }
+}
+
+/// Mining operation of the platform, it only runs while the platform is stopped on a deposit
+#[derive(Component, Debug, Default)]
+pub struct Mining {
+    pub active: bool,
+    /// Part of a unit that is mined but not stored yet
+    extracted: f32,
+}
+
+impl Mining {
+    pub fn stop(&mut self) {
+        self.active = false;
+        self.extracted = 0.0;
+    }
+}
+
+fn is_stopped(map_pos: &MapPos, movement: &MapMovement) -> bool {
+    movement.halt && map_pos.progress == 0.5
+}
+
+fn toggle_mining(
+    input: Res<Input<KeyCode>>,
+    mut platform: Query<
+        (&MapPos, &MapMovement, &PlatformModules, &mut Mining),
+        With<MiningPlatform>,
+    >,
+    generated_chunks: Res<GeneratedChunks>,
+) {
+    if !input.just_pressed(KeyCode::E) {
+        return;
+    }
+    let Ok((map_pos, movement, modules, mut mining)) = platform.get_single_mut() else {
+        return;
+    };
+    if mining.active {
+        mining.stop();
+        info!("Stopped mining");
+        return;
+    }
+    if !is_stopped(map_pos, movement) {
+        warn!("The platform has to stop before it can mine");
+        return;
+    }
+    if modules.effect(ModuleKind::Drill) == 0.0 {
+        warn!("The drill is broken or has no power");
+        return;
+    }
+    let (chunk_pos, tile_pos) = chunk_and_local_from_global(map_pos.pos);
+    match generated_chunks
+        .deposits
+        .get(&chunk_pos)
+        .and_then(|deposits| deposits.get(tile_pos))
+    {
+        Some(deposit) if !deposit.is_depleted() => {
+            mining.active = true;
+            info!("Started mining {:?}", deposit.ore);
+        }
+        _ => warn!("There is nothing to mine here"),
+    }
+}
+
+/// Moves ore from the deposit under the platform to its cargo hold
+fn mine(
+    time: Res<Time>,
+    mut platform: Query<
+        (
+            &MapPos,
+            &MapMovement,
+            &PlatformModules,
+            &mut Mining,
+            &mut Inventory,
+        ),
+        With<MiningPlatform>,
+    >,
+    mut generated_chunks: ResMut<GeneratedChunks>,
+    chunks: Query<(&Chunk, &TileStorage)>,
+    mut tile_deposits: Query<&mut Deposit>,
+) {
+    for (map_pos, movement, modules, mut mining, mut inventory) in platform.iter_mut() {
+        if !mining.active {
+            continue;
+        }
+        let drill = modules.effect(ModuleKind::Drill);
+        if drill == 0.0 {
+            mining.stop();
+            warn!("Stopped mining, the drill broke down");
+            continue;
+        }
+        if !is_stopped(map_pos, movement) {
+            mining.stop();
+            info!("Stopped mining, the platform is moving");
+            continue;
+        }
+        let (chunk_pos, tile_pos) = chunk_and_local_from_global(map_pos.pos);
+        let Some(deposit) = generated_chunks
+            .deposits
+            .get_mut(&chunk_pos)
+            .and_then(|deposits| deposits.get_mut(tile_pos))
+        else {
+            mining.stop();
+            continue;
+        };
+
+        let item = Item::Ore(deposit.ore);
+        if inventory.space_for(item) == 0 {
+            mining.stop();
+            warn!("Stopped mining, the cargo hold is full");
+            continue;
+        }
+
+        mining.extracted += MINING_RATE * drill * time.delta_seconds();
+        let amount = (mining.extracted as u32)
+            .min(deposit.remaining)
+            .min(inventory.space_for(item));
+        if amount > 0 {
+            if let Err(e) = inventory.add(item, amount) {
+                mining.stop();
+                warn!("Stopped mining, can't store ore: {}", e);
+                continue;
+            }
+            mining.extracted -= amount as f32;
+            deposit.remaining -= amount;
+            // Keep the tile up to date for the map
+            if let Some(mut tile_deposit) =
+                find_tile(&chunks, map_pos.pos).and_then(|tile| tile_deposits.get_mut(tile).ok())
+            {
+                *tile_deposit = *deposit;
+            }
+        }
+        if deposit.is_depleted() {
+            mining.stop();
+            info!("{:?} deposit is depleted", deposit.ore);
+        }
+    }
+}
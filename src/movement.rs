@@ -0,0 +1,231 @@
+use super::{
+    chunk_management::GeneratedChunks,
+    inventory::{Inventory, Item},
+    navigation::RouteOrders,
+    neighbour,
+    radio::RadioMessage,
+    rotate_direction,
+    terrain::TerrainMap,
+    MapPos, MovementConstraints, PlayerVehicle, WorldSeed,
+};
+use bevy::prelude::*;
+use bevy_ecs_tilemap::helpers::hex_grid::neighbors::{HexDirection, HexRowDirection};
+use serde::{Deserialize, Serialize};
+
+pub struct MovementPlugin;
+
+impl Plugin for MovementPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system(move_on_map);
+    }
+}
+
+/// How something moves on a map
+#[derive(Component, Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct MapMovement {
+    /// Tiles per second
+    pub speed: f32,
+    pub constraints: MovementConstraints,
+    /// Stop at the center of the current tile and stay there while set
+    pub halt: bool,
+}
+
+/// Why a vehicle stopped at the center of a tile without being told to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Blocked {
+    /// Next tile can't be entered with the vehicle's constraints
+    Impassable,
+    /// Not enough fuel to cross the next tile
+    OutOfFuel,
+}
+
+impl MapMovement {
+    pub fn can_turn(&self, from: HexRowDirection, to: HexRowDirection) -> bool {
+        match self.constraints {
+            MovementConstraints::Free => true,
+            MovementConstraints::Platform => {
+                matches!(
+                    (HexDirection::from(to) as i32 - HexDirection::from(from) as i32).rem_euclid(6),
+                    0 | 1 | 5
+                )
+            }
+        }
+    }
+}
+
+impl MapPos {
+    /// Direction the thing is actually moving in, opposite of where it faces when reversing
+    pub fn travel_direction(&self) -> HexRowDirection {
+        if self.reverse {
+            rotate_direction(self.current_direction, 3)
+        } else {
+            self.current_direction
+        }
+    }
+
+    /// Moves forward for `seconds`, or backwards when reversing, at a speed that depends on the
+    /// terrain. Target direction is applied when passing tile center, and it stops at tile center
+    /// when the next tile can't be entered. Fuel for the next tile is paid with `pay_fuel` when
+    /// setting off from tile center, it returns false when there isn't enough.
+    pub fn advance(
+        &mut self,
+        mut seconds: f32,
+        movement: &MapMovement,
+        terrain: &TerrainMap,
+        mut pay_fuel: impl FnMut(u32) -> bool,
+    ) -> Option<Blocked> {
+        let sign = if self.reverse { -1.0 } else { 1.0 };
+        loop {
+            let speed = movement.speed * terrain.properties_at(self.pos).speed;
+            let mut distance = seconds * speed;
+            let to_center = (0.5 - self.progress) * sign;
+            if to_center >= 0.0 {
+                if distance < to_center {
+                    self.progress += distance * sign;
+                    return None;
+                }
+                distance -= to_center;
+                seconds -= to_center / speed;
+                self.progress = 0.5;
+                if movement.halt {
+                    return None;
+                }
+                if let Some(target) = self.target_direction.take() {
+                    if movement.can_turn(self.current_direction, target) {
+                        self.current_direction = target
+                    } else {
+                        warn!(
+                            "Can't turn from {:?} to {:?} with {:?} constraints",
+                            self.current_direction, target, movement.constraints
+                        );
+                    }
+                }
+                let next = terrain.properties_at(neighbour(self.pos, self.travel_direction()));
+                if !next.is_passable(movement.constraints) {
+                    return Some(Blocked::Impassable);
+                }
+                if !pay_fuel(next.fuel_cost) {
+                    return Some(Blocked::OutOfFuel);
+                }
+            }
+            let to_edge = if self.reverse {
+                self.progress
+            } else {
+                1.0 - self.progress
+            };
+            if distance < to_edge {
+                self.progress += distance * sign;
+                return None;
+            }
+            seconds -= to_edge / speed;
+            self.pos = neighbour(self.pos, self.travel_direction());
+            self.progress = if self.reverse { 1.0 } else { 0.0 };
+        }
+    }
+}
+
+/// Moves vehicles and cancels their routes when they get stuck. Only the player's vehicle uses
+/// fuel, npcs are assumed to refuel on their own.
+fn move_on_map(
+    mut movers: Query<(
+        &mut MapPos,
+        &MapMovement,
+        Option<&mut RouteOrders>,
+        Option<&mut Inventory>,
+        Option<&PlayerVehicle>,
+    )>,
+    time: Res<Time>,
+    world_seed: Res<WorldSeed>,
+    generated_chunks: Res<GeneratedChunks>,
+    mut radio: EventWriter<RadioMessage>,
+) {
+    let terrain = TerrainMap::new(&world_seed, &generated_chunks);
+    for (mut map_pos, movement, orders, mut inventory, player) in movers.iter_mut() {
+        // Vehicles without a way to move stay where they are
+        if (movement.halt && map_pos.progress == 0.5) || movement.speed <= 0.0 {
+            continue;
+        }
+        let blocked = map_pos.advance(
+            time.delta_seconds(),
+            movement,
+            &terrain,
+            |fuel_cost| match (player, inventory.as_mut()) {
+                (Some(_), Some(inventory)) => inventory.remove(Item::Fuel, fuel_cost).is_ok(),
+                _ => true,
+            },
+        );
+        let Some(blocked) = blocked else {
+            continue;
+        };
+        // The route can't be followed any further, halting is left to following the empty route
+        if let Some(mut orders) = orders {
+            if !orders.0.is_empty() {
+                orders.0.clear();
+            }
+        }
+        if player.is_some() {
+            let text = match blocked {
+                Blocked::Impassable => "We can't go any further this way, route cancelled.",
+                Blocked::OutOfFuel => "We're out of fuel, route cancelled.",
+            };
+            radio.send(RadioMessage {
+                from: "Deck".to_string(),
+                text: text.to_string(),
+            });
+        } else {
+            debug!("Npc at {:?} stopped: {:?}", map_pos.pos, blocked);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{test_support::test_world, TileKind};
+    use bevy_ecs_tilemap::helpers::hex_grid::offset::RowEvenPos;
+
+    #[test]
+    fn fuel_is_paid_per_tile_until_blocked() {
+        let mut world = test_world(1);
+        let mut map_pos = MapPos {
+            pos: RowEvenPos { q: 8, r: 8 },
+            ..default()
+        };
+        let ahead = |steps| {
+            (0..steps).fold(map_pos.pos, |pos, _| {
+                neighbour(pos, map_pos.current_direction)
+            })
+        };
+        world.generated_chunks.set_kind(ahead(1), TileKind::Dunes);
+        world
+            .generated_chunks
+            .set_kind(ahead(3), TileKind::DriedCanyon);
+        let blocked_at = ahead(2);
+        let terrain = world.terrain();
+        let movement = MapMovement {
+            speed: 1.0,
+            constraints: MovementConstraints::Platform,
+            halt: false,
+        };
+
+        let mut paid = Vec::new();
+        let blocked = map_pos.advance(10.0, &movement, &terrain, |fuel_cost| {
+            paid.push(fuel_cost);
+            true
+        });
+        assert_eq!(blocked, Some(Blocked::Impassable));
+        assert_eq!(map_pos.pos, blocked_at);
+        assert_eq!(map_pos.progress, 0.5);
+        assert_eq!(paid, [2, 1]);
+
+        let mut map_pos = MapPos {
+            pos: blocked_at,
+            reverse: true,
+            progress: 0.5,
+            ..default()
+        };
+        let blocked = map_pos.advance(10.0, &movement, &terrain, |_| false);
+        assert_eq!(blocked, Some(Blocked::OutOfFuel));
+        assert_eq!(map_pos.pos, blocked_at);
+    }
+}
@@ -0,0 +1,252 @@
+#![allow(clippy::too_many_arguments)]
+
+use std::collections::VecDeque;
+
+use super::{
+    chunk_management::{world_to_global_pos, GeneratedChunks, TILEMAP_GRID_SIZE},
+    neighbour,
+    terrain::TerrainMap,
+    CurrentView, MapPos, MovementConstraints, PathfindingPos, PlayerVehicle, WorldSeed, MOVE_COST,
+};
+use crate::movement::MapMovement;
+use bevy::{prelude::*, window::PrimaryWindow};
+use bevy_ecs_tilemap::helpers::hex_grid::{axial::AxialPos, offset::RowEvenPos};
+use bevy_prototype_lyon::prelude::*;
+use pathfinding::prelude::astar;
+
+/// How far a route may stray from the straight line, in tiles. Keeps searches for unreachable
+/// goals from going on forever.
+const MAX_DETOUR: u32 = 32;
+
+pub struct NavigationPlugin;
+
+impl Plugin for NavigationPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system(order_travel)
+            .add_system(cancel_route)
+            .add_system(follow_route.after(order_travel).after(cancel_route))
+            .add_system(draw_route.after(follow_route));
+    }
+}
+
+/// Steps of a planned route that are yet to be reached, in order
+#[derive(Component, Debug, Default)]
+pub struct RouteOrders(pub VecDeque<PathfindingPos>);
+
+/// Marker struct for the line that shows the player's planned route on the map
+#[derive(Component)]
+pub struct RouteLine;
+
+/// Distance between two tiles, in tiles
+pub fn hex_distance(a: RowEvenPos, b: RowEvenPos) -> u32 {
+    AxialPos::from(a).distance_from(&AxialPos::from(b)) as u32
+}
+
+/// Where route planning starts from. A vehicle that is already past the center of its tile can't
+/// turn anymore, so the route starts at the tile it is leaving to.
+pub fn route_start(map_pos: &MapPos) -> PathfindingPos {
+    let start = PathfindingPos {
+        pos: map_pos.pos,
+        direction: map_pos.current_direction,
+        reverse: map_pos.reverse,
+    };
+    if map_pos.progress > 0.5 {
+        PathfindingPos {
+            pos: neighbour(map_pos.pos, map_pos.travel_direction()),
+            ..start
+        }
+    } else {
+        start
+    }
+}
+
+/// Plans a route from `start` to `goal` around impassable terrain. Returned steps include the
+/// starting position.
+pub fn plan_route(
+    start: &MapPos,
+    goal: RowEvenPos,
+    constraints: MovementConstraints,
+    terrain: &TerrainMap,
+) -> Option<Vec<PathfindingPos>> {
+    if !terrain.properties_at(goal).is_passable(constraints) {
+        return None;
+    }
+    let start = route_start(start);
+    let max_length = hex_distance(start.pos, goal) + MAX_DETOUR;
+    astar(
+        &start,
+        |pos| {
+            let mut successors = pos.successors(constraints, terrain);
+            successors.retain(|(next, _)| {
+                hex_distance(start.pos, next.pos) + hex_distance(next.pos, goal) <= max_length
+            });
+            successors
+        },
+        |pos| hex_distance(pos.pos, goal) * MOVE_COST,
+        |pos| pos.pos == goal,
+    )
+    .map(|(route, _cost)| route)
+}
+
+fn order_travel(
+    mut commands: Commands,
+    mouse: Res<Input<MouseButton>>,
+    current_view: Res<CurrentView>,
+    window: Query<&Window, With<PrimaryWindow>>,
+    camera: Query<(&Camera, &GlobalTransform), With<Camera2d>>,
+    player: Query<(Entity, &MapPos, &MapMovement), With<PlayerVehicle>>,
+    world_seed: Res<WorldSeed>,
+    generated_chunks: Res<GeneratedChunks>,
+    interactions: Query<&Interaction>,
+) {
+    if !(matches!(*current_view, CurrentView::Map) && mouse.just_pressed(MouseButton::Left)) {
+        return;
+    }
+    // Clicks on buttons are not travel orders
+    if interactions
+        .iter()
+        .any(|interaction| !matches!(interaction, Interaction::None))
+    {
+        return;
+    }
+    let (Ok((camera, camera_transform)), Ok(window)) = (camera.get_single(), window.get_single())
+    else {
+        return;
+    };
+    let Some(world_pos) = window
+        .cursor_position()
+        .and_then(|cursor_pos| camera.viewport_to_world_2d(camera_transform, cursor_pos))
+    else {
+        return;
+    };
+    let goal = world_to_global_pos(world_pos);
+    // Only a single vehicle can be ordered around for now
+    let Ok((player_entity, player_pos, movement)) = player.get_single() else {
+        return;
+    };
+    let terrain = TerrainMap::new(&world_seed, &generated_chunks);
+    match plan_route(player_pos, goal, movement.constraints, &terrain) {
+        Some(route) => {
+            commands
+                .entity(player_entity)
+                .insert(RouteOrders(route.into()));
+        }
+        None => warn!("No route to {:?}", goal),
+    }
+}
+
+fn cancel_route(
+    input: Res<Input<KeyCode>>,
+    mut orders: Query<&mut RouteOrders, With<PlayerVehicle>>,
+) {
+    if input.just_pressed(KeyCode::C) {
+        for mut orders in orders.iter_mut() {
+            orders.0.clear();
+        }
+    }
+}
+
+/// Steers vehicles along their planned routes, stopping at the end
+fn follow_route(mut vehicles: Query<(&mut MapPos, &mut MapMovement, &mut RouteOrders)>) {
+    for (mut map_pos, mut movement, mut orders) in vehicles.iter_mut() {
+        while orders
+            .0
+            .front()
+            .is_some_and(|step| step.pos == map_pos.pos && step.reverse == map_pos.reverse)
+        {
+            orders.0.pop_front();
+        }
+        match orders.0.front() {
+            None => {
+                if !movement.halt {
+                    movement.halt = true;
+                }
+                if map_pos.target_direction.is_some() {
+                    map_pos.target_direction = None;
+                }
+            }
+            // Reverse can only be switched while standing at tile center
+            Some(step) if step.pos == map_pos.pos => {
+                if !movement.halt {
+                    movement.halt = true;
+                }
+                if map_pos.progress == 0.5 {
+                    map_pos.reverse = step.reverse;
+                }
+            }
+            Some(step) => {
+                if movement.halt {
+                    movement.halt = false;
+                }
+                if map_pos.target_direction != Some(step.direction) {
+                    map_pos.target_direction = Some(step.direction);
+                }
+            }
+        }
+    }
+}
+
+fn draw_route(
+    player: Query<(&MapPos, &RouteOrders), (With<PlayerVehicle>, Changed<RouteOrders>)>,
+    mut route_line: Query<&mut Path, With<RouteLine>>,
+) {
+    if let Ok((player_pos, orders)) = player.get_single() {
+        let mut path_builder = PathBuilder::new();
+        if !orders.0.is_empty() {
+            path_builder.move_to(player_pos.pos.center_in_world(&TILEMAP_GRID_SIZE));
+            for step in orders.0.iter() {
+                path_builder.line_to(step.pos.center_in_world(&TILEMAP_GRID_SIZE));
+            }
+        }
+        *route_line.single_mut() = path_builder.build();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{test_support::test_world, TileKind};
+
+    #[test]
+    fn route_goes_around_blocked_tile() {
+        let mut world = test_world(1);
+        let start = MapPos {
+            pos: RowEvenPos { q: 8, r: 8 },
+            ..default()
+        };
+        let ahead =
+            |steps| (0..steps).fold(start.pos, |pos, _| neighbour(pos, start.current_direction));
+        let blocked = ahead(2);
+        let goal = ahead(4);
+        world
+            .generated_chunks
+            .set_kind(blocked, TileKind::DriedCanyon);
+        let terrain = world.terrain();
+
+        let route = plan_route(&start, goal, MovementConstraints::Platform, &terrain)
+            .expect("No route around a single tile");
+        assert_eq!(route.first().unwrap().pos, start.pos);
+        assert_eq!(route.last().unwrap().pos, goal);
+        assert!(route.iter().all(|step| step.pos != blocked));
+        // Every step is a move the platform can actually make
+        for pair in route.windows(2) {
+            assert!(pair[0]
+                .successors(MovementConstraints::Platform, &terrain)
+                .iter()
+                .any(|(next, _)| *next == pair[1]));
+        }
+    }
+
+    #[test]
+    fn no_route_into_impassable_goal() {
+        let mut world = test_world(1);
+        let goal = RowEvenPos { q: 12, r: 8 };
+        world.generated_chunks.set_kind(goal, TileKind::DriedCanyon);
+        let terrain = world.terrain();
+        let start = MapPos {
+            pos: RowEvenPos { q: 8, r: 8 },
+            ..default()
+        };
+        assert!(plan_route(&start, goal, MovementConstraints::Platform, &terrain).is_none());
+    }
+}
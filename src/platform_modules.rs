@@ -0,0 +1,654 @@
+use std::{collections::BTreeMap, error::Error, fmt};
+
+use super::{
+    combat::{Encounter, Hull},
+    inventory::{Inventory, InventoryError, Item},
+    mining::Mining,
+    movement::MapMovement,
+    ui::{text_style, BUTTON_COLOR, HOVERED_BUTTON_COLOR, PANEL_COLOR},
+    FontAssets, MiningPlatform, PLATFORM_MAP_SPEED,
+};
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// Condition a single repair adds
+const REPAIR_STEP: f32 = 0.25;
+/// Modules in worse condition than this don't work at all
+const BROKEN_CONDITION: f32 = 0.1;
+/// Power the engines make in perfect condition, enough for everything else
+const ENGINE_POWER: f32 = 14.0;
+/// Part of the full speed the platform still makes on worn out tracks
+const MIN_TRACK_SPEED: f32 = 0.3;
+/// Tiles a radar in perfect condition adds to what the lookouts see
+const RADAR_CHART_RANGE: f32 = 4.0;
+/// Hit points one patch of the hull restores, and what it takes
+const HULL_PATCH: u32 = 50;
+const HULL_PATCH_COST: [(Item, u32); 1] = [(Item::Scrap, 5)];
+
+pub struct PlatformModulesPlugin;
+
+impl Plugin for PlatformModulesPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_startup_system(spawn_modules_panel)
+            .add_system(run_modules)
+            .add_system(toggle_modules_panel)
+            .add_system(repair_buttons.after(run_modules))
+            .add_system(update_modules_panel.after(repair_buttons));
+    }
+}
+
+/// Parts of the platform, in the order they get power
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum ModuleKind {
+    /// Make power for everything else
+    Engines,
+    Tracks,
+    Drill,
+    Radio,
+    Radar,
+    Turrets,
+    LivingQuarters,
+}
+
+impl ModuleKind {
+    const ALL: [Self; 7] = [
+        Self::Engines,
+        Self::Tracks,
+        Self::Drill,
+        Self::Radio,
+        Self::Radar,
+        Self::Turrets,
+        Self::LivingQuarters,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Engines => "engines",
+            Self::Tracks => "tracks",
+            Self::Drill => "drill",
+            Self::Radio => "radio",
+            Self::Radar => "radar",
+            Self::Turrets => "turrets",
+            Self::LivingQuarters => "living quarters",
+        }
+    }
+
+    /// Power needed to work
+    fn power_draw(self) -> f32 {
+        match self {
+            Self::Engines => 0.0,
+            Self::Tracks => 3.0,
+            Self::Drill => 4.0,
+            Self::Radio => 1.0,
+            Self::Radar => 2.0,
+            Self::Turrets => 2.0,
+            Self::LivingQuarters => 2.0,
+        }
+    }
+
+    /// Condition lost per second of use
+    fn wear(self) -> f32 {
+        match self {
+            Self::Engines => 1.0 / 3600.0,
+            Self::Tracks => 1.0 / 1200.0,
+            Self::Drill => 1.0 / 900.0,
+            Self::Radio => 1.0 / 7200.0,
+            Self::Radar => 1.0 / 5400.0,
+            Self::Turrets => 1.0 / 120.0,
+            Self::LivingQuarters => 1.0 / 7200.0,
+        }
+    }
+
+    /// Items one repair takes
+    fn repair_cost(self) -> &'static [(Item, u32)] {
+        match self {
+            Self::Engines => &[(Item::SpareParts, 2)],
+            Self::Tracks => &[(Item::SpareParts, 1), (Item::Scrap, 4)],
+            Self::Drill => &[(Item::SpareParts, 2)],
+            Self::Radio => &[(Item::SpareParts, 1)],
+            Self::Radar => &[(Item::SpareParts, 2)],
+            Self::Turrets => &[(Item::SpareParts, 1), (Item::Scrap, 3)],
+            Self::LivingQuarters => &[(Item::Scrap, 3)],
+        }
+    }
+
+    /// How the convoy found the abandoned platform
+    fn starting_condition(self) -> f32 {
+        match self {
+            Self::Engines => 0.6,
+            Self::Tracks => 0.5,
+            Self::Drill => 0.0,
+            Self::Radio => 0.0,
+            Self::Radar => 0.0,
+            Self::Turrets => 0.3,
+            Self::LivingQuarters => 0.4,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Module {
+    /// From 0.0 for wrecked to 1.0 for good as new
+    pub condition: f32,
+    /// Set when the engines make enough power for the module
+    #[serde(skip)]
+    pub powered: bool,
+}
+
+impl Module {
+    pub fn is_broken(&self) -> bool {
+        self.condition < BROKEN_CONDITION
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RepairError {
+    /// Already in perfect condition
+    NothingToRepair,
+    Inventory(InventoryError),
+}
+
+impl fmt::Display for RepairError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NothingToRepair => write!(f, "nothing to repair"),
+            Self::Inventory(e) => e.fmt(f),
+        }
+    }
+}
+
+impl Error for RepairError {}
+
+impl From<InventoryError> for RepairError {
+    fn from(e: InventoryError) -> Self {
+        Self::Inventory(e)
+    }
+}
+
+/// Takes all of the cost from the inventory or nothing
+fn pay(inventory: &mut Inventory, cost: &[(Item, u32)]) -> Result<(), InventoryError> {
+    for (item, amount) in cost {
+        let available = inventory.count(*item);
+        if available < *amount {
+            return Err(InventoryError::NotEnough {
+                item: *item,
+                available,
+            });
+        }
+    }
+    for (item, amount) in cost {
+        inventory.remove(*item, *amount)?;
+    }
+    Ok(())
+}
+
+fn cost_text(cost: &[(Item, u32)]) -> String {
+    let parts: Vec<String> = cost
+        .iter()
+        .map(|(item, amount)| format!("{} {}", amount, item.name()))
+        .collect();
+    parts.join(", ")
+}
+
+/// Systems of the platform, each with its condition
+#[derive(Component, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PlatformModules {
+    modules: BTreeMap<ModuleKind, Module>,
+}
+
+impl Default for PlatformModules {
+    fn default() -> Self {
+        let mut modules = Self {
+            modules: ModuleKind::ALL
+                .into_iter()
+                .map(|kind| {
+                    (
+                        kind,
+                        Module {
+                            condition: kind.starting_condition(),
+                            powered: false,
+                        },
+                    )
+                })
+                .collect(),
+        };
+        modules.allocate_power();
+        modules
+    }
+}
+
+impl PlatformModules {
+    pub fn get(&self, kind: ModuleKind) -> Module {
+        self.modules.get(&kind).copied().unwrap_or(Module {
+            condition: 0.0,
+            powered: false,
+        })
+    }
+
+    /// How well a module works, from 0.0 when broken or without power to 1.0
+    pub fn effect(&self, kind: ModuleKind) -> f32 {
+        let module = self.get(kind);
+        if module.is_broken() || !module.powered {
+            0.0
+        } else {
+            module.condition
+        }
+    }
+
+    /// Power the engines make
+    pub fn power_supply(&self) -> f32 {
+        let engines = self.get(ModuleKind::Engines);
+        if engines.is_broken() {
+            0.0
+        } else {
+            ENGINE_POWER * engines.condition
+        }
+    }
+
+    /// Power used by modules that got it
+    pub fn power_used(&self) -> f32 {
+        self.modules
+            .iter()
+            .filter(|(_, module)| module.powered)
+            .map(|(kind, _)| kind.power_draw())
+            .sum()
+    }
+
+    /// Gives power to modules that work, in order, until there is no more
+    fn allocate_power(&mut self) {
+        let mut left = self.power_supply();
+        for (kind, module) in self.modules.iter_mut() {
+            let draw = kind.power_draw();
+            module.powered = !module.is_broken() && draw <= left;
+            if module.powered {
+                left -= draw;
+            }
+        }
+    }
+
+    fn wear(&mut self, kind: ModuleKind, seconds: f32) {
+        if let Some(module) = self.modules.get_mut(&kind) {
+            module.condition = (module.condition - kind.wear() * seconds).max(0.0);
+        }
+    }
+
+    /// Fixes a module up a bit using parts from the inventory
+    pub fn repair(
+        &mut self,
+        kind: ModuleKind,
+        inventory: &mut Inventory,
+    ) -> Result<(), RepairError> {
+        let module = self.modules.entry(kind).or_insert(Module {
+            condition: 0.0,
+            powered: false,
+        });
+        if module.condition >= 1.0 {
+            return Err(RepairError::NothingToRepair);
+        }
+        pay(inventory, kind.repair_cost())?;
+        module.condition = (module.condition + REPAIR_STEP).min(1.0);
+        self.allocate_power();
+        Ok(())
+    }
+
+    /// Tiles the radar adds to the chart range
+    pub fn radar_range(&self) -> f32 {
+        RADAR_CHART_RANGE * self.effect(ModuleKind::Radar)
+    }
+
+    /// Tiles per second on open sand, the platform doesn't move without engines and tracks
+    pub fn map_speed(&self) -> f32 {
+        let tracks = self.effect(ModuleKind::Tracks);
+        if self.get(ModuleKind::Engines).is_broken() || tracks == 0.0 {
+            0.0
+        } else {
+            PLATFORM_MAP_SPEED * (MIN_TRACK_SPEED + (1.0 - MIN_TRACK_SPEED) * tracks)
+        }
+    }
+}
+
+/// Patches the hull with scrap
+fn patch_hull(hull: &mut Hull, inventory: &mut Inventory) -> Result<(), RepairError> {
+    if hull.hp >= hull.max_hp {
+        return Err(RepairError::NothingToRepair);
+    }
+    pay(inventory, &HULL_PATCH_COST)?;
+    hull.hp = (hull.hp + HULL_PATCH).min(hull.max_hp);
+    Ok(())
+}
+
+/// Wears down modules that are used, and applies what they do to the platform
+fn run_modules(
+    time: Res<Time>,
+    mut platform: Query<(&mut PlatformModules, &mut MapMovement, &Mining), With<MiningPlatform>>,
+    encounters: Query<(), With<Encounter>>,
+) {
+    let seconds = time.delta_seconds();
+    for (mut modules, mut movement, mining) in platform.iter_mut() {
+        let mut in_use = vec![
+            ModuleKind::Radio,
+            ModuleKind::Radar,
+            ModuleKind::LivingQuarters,
+        ];
+        if !movement.halt {
+            in_use.extend([ModuleKind::Engines, ModuleKind::Tracks]);
+        }
+        if mining.active {
+            in_use.extend([ModuleKind::Engines, ModuleKind::Drill]);
+        }
+        if !encounters.is_empty() {
+            in_use.push(ModuleKind::Turrets);
+        }
+        for kind in in_use {
+            if modules.effect(kind) > 0.0 {
+                modules.wear(kind, seconds);
+            }
+        }
+        modules.allocate_power();
+
+        let speed = modules.map_speed();
+        if movement.speed != speed {
+            movement.speed = speed;
+        }
+    }
+}
+
+/// Something on the modules panel that can be repaired
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Repairable {
+    Module(ModuleKind),
+    Hull,
+}
+
+#[derive(Component)]
+struct ModulesPanel;
+
+/// Text with the power of the platform
+#[derive(Component)]
+struct PowerText;
+
+/// Text with the condition of a module or the hull
+#[derive(Component)]
+struct RepairableText(Repairable);
+
+#[derive(Component)]
+struct RepairButton(Repairable);
+
+fn spawn_modules_panel(mut commands: Commands, fonts: Res<FontAssets>) {
+    let rows = ModuleKind::ALL
+        .into_iter()
+        .map(Repairable::Module)
+        .chain([Repairable::Hull]);
+    commands
+        .spawn((
+            ModulesPanel,
+            NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    position: UiRect {
+                        right: Val::Px(20.0),
+                        bottom: Val::Px(20.0),
+                        ..default()
+                    },
+                    flex_direction: FlexDirection::Column,
+                    padding: UiRect::all(Val::Px(10.0)),
+                    ..default()
+                },
+                background_color: PANEL_COLOR.into(),
+                visibility: Visibility::Hidden,
+                ..default()
+            },
+        ))
+        .with_children(|panel| {
+            panel.spawn((
+                PowerText,
+                TextBundle::from_section("", text_style(&fonts)).with_style(Style {
+                    margin: UiRect::bottom(Val::Px(8.0)),
+                    ..default()
+                }),
+            ));
+            for repairable in rows {
+                let cost = match repairable {
+                    Repairable::Module(kind) => cost_text(kind.repair_cost()),
+                    Repairable::Hull => cost_text(&HULL_PATCH_COST),
+                };
+                panel
+                    .spawn(NodeBundle {
+                        style: Style {
+                            flex_direction: FlexDirection::Row,
+                            align_items: AlignItems::Center,
+                            margin: UiRect::bottom(Val::Px(4.0)),
+                            ..default()
+                        },
+                        ..default()
+                    })
+                    .with_children(|row| {
+                        row.spawn((
+                            RepairableText(repairable),
+                            TextBundle::from_section("", text_style(&fonts)),
+                        ));
+                        row.spawn((
+                            RepairButton(repairable),
+                            ButtonBundle {
+                                style: Style {
+                                    margin: UiRect::left(Val::Px(6.0)),
+                                    padding: UiRect::new(
+                                        Val::Px(6.0),
+                                        Val::Px(6.0),
+                                        Val::Px(2.0),
+                                        Val::Px(2.0),
+                                    ),
+                                    ..default()
+                                },
+                                background_color: BUTTON_COLOR.into(),
+                                ..default()
+                            },
+                        ))
+                        .with_children(|button| {
+                            button.spawn(TextBundle::from_section(
+                                format!("Repair: {}", cost),
+                                text_style(&fonts),
+                            ));
+                        });
+                    });
+            }
+        });
+}
+
+fn toggle_modules_panel(
+    input: Res<Input<KeyCode>>,
+    mut panel: Query<&mut Visibility, With<ModulesPanel>>,
+) {
+    if input.just_pressed(KeyCode::R) {
+        for mut visibility in panel.iter_mut() {
+            *visibility = match *visibility {
+                Visibility::Hidden => Visibility::Visible,
+                _ => Visibility::Hidden,
+            };
+        }
+    }
+}
+
+fn repair_buttons(
+    mut buttons: Query<(&Interaction, &RepairButton, &mut BackgroundColor), Changed<Interaction>>,
+    mut platform: Query<(&mut PlatformModules, &mut Hull, &mut Inventory), With<MiningPlatform>>,
+) {
+    for (interaction, RepairButton(repairable), mut color) in buttons.iter_mut() {
+        match interaction {
+            Interaction::Clicked => {
+                *color = HOVERED_BUTTON_COLOR.into();
+                let Ok((mut modules, mut hull, mut inventory)) = platform.get_single_mut() else {
+                    continue;
+                };
+                let result = match repairable {
+                    Repairable::Module(kind) => modules.repair(*kind, &mut inventory),
+                    Repairable::Hull => patch_hull(&mut hull, &mut inventory),
+                };
+                match result {
+                    Ok(()) => info!("Repaired {:?}", repairable),
+                    Err(e) => warn!("Can't repair {:?}: {}", repairable, e),
+                }
+            }
+            Interaction::Hovered => *color = HOVERED_BUTTON_COLOR.into(),
+            Interaction::None => *color = BUTTON_COLOR.into(),
+        }
+    }
+}
+
+fn update_modules_panel(
+    panel: Query<&Visibility, With<ModulesPanel>>,
+    platform: Query<(&PlatformModules, &Hull), With<MiningPlatform>>,
+    mut power_text: Query<&mut Text, With<PowerText>>,
+    mut rows: Query<(&mut Text, &RepairableText), Without<PowerText>>,
+) {
+    let Ok(visibility) = panel.get_single() else {
+        return;
+    };
+    if *visibility == Visibility::Hidden {
+        return;
+    }
+    let Ok((modules, hull)) = platform.get_single() else {
+        return;
+    };
+    power_text.single_mut().sections[0].value = format!(
+        "Power: {:.1}/{:.1}  R: close",
+        modules.power_used(),
+        modules.power_supply()
+    );
+    for (mut text, RepairableText(repairable)) in rows.iter_mut() {
+        text.sections[0].value = match repairable {
+            Repairable::Module(kind) => {
+                let module = modules.get(*kind);
+                let state = if module.is_broken() {
+                    "broken"
+                } else if module.powered {
+                    "on"
+                } else {
+                    "no power"
+                };
+                format!(
+                    "{:<16} {:>3}% {:<8}",
+                    kind.name(),
+                    (module.condition * 100.0).round(),
+                    state
+                )
+            }
+            Repairable::Hull => {
+                format!("{:<16} {:>3}/{:<4}", "hull", hull.hp, hull.max_hp)
+            }
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Modules in the given condition, in the order of [`ModuleKind::ALL`]
+    fn with_conditions(conditions: [f32; 7]) -> PlatformModules {
+        let mut modules = PlatformModules {
+            modules: ModuleKind::ALL
+                .into_iter()
+                .zip(conditions)
+                .map(|(kind, condition)| {
+                    (
+                        kind,
+                        Module {
+                            condition,
+                            powered: false,
+                        },
+                    )
+                })
+                .collect(),
+        };
+        modules.allocate_power();
+        modules
+    }
+
+    fn powered(modules: &PlatformModules) -> Vec<ModuleKind> {
+        ModuleKind::ALL
+            .into_iter()
+            .filter(|kind| modules.get(*kind).powered)
+            .collect()
+    }
+
+    #[test]
+    fn cost_is_paid_in_full_or_not_at_all() {
+        let mut inventory =
+            Inventory::with_items(1_000, 1_000, &[(Item::SpareParts, 1), (Item::Scrap, 2)])
+                .unwrap();
+        let cost = [(Item::SpareParts, 1), (Item::Scrap, 4)];
+        assert_eq!(
+            pay(&mut inventory, &cost),
+            Err(InventoryError::NotEnough {
+                item: Item::Scrap,
+                available: 2
+            })
+        );
+        assert_eq!(inventory.count(Item::SpareParts), 1);
+        assert_eq!(inventory.count(Item::Scrap), 2);
+
+        inventory.add(Item::Scrap, 2).unwrap();
+        assert_eq!(pay(&mut inventory, &cost), Ok(()));
+        assert_eq!(inventory.count(Item::SpareParts), 0);
+        assert_eq!(inventory.count(Item::Scrap), 0);
+    }
+
+    #[test]
+    fn power_goes_to_modules_in_order() {
+        // Half the engine power is enough for the tracks and the drill
+        let modules = with_conditions([0.5, 1.0, 1.0, 1.0, 1.0, 1.0, 1.0]);
+        assert_eq!(modules.power_supply(), 7.0);
+        assert_eq!(
+            powered(&modules),
+            [ModuleKind::Engines, ModuleKind::Tracks, ModuleKind::Drill]
+        );
+        assert_eq!(modules.power_used(), 7.0);
+
+        // Power of a broken drill goes to the next modules
+        let modules = with_conditions([0.5, 1.0, BROKEN_CONDITION / 2.0, 1.0, 1.0, 1.0, 1.0]);
+        assert_eq!(
+            powered(&modules),
+            [
+                ModuleKind::Engines,
+                ModuleKind::Tracks,
+                ModuleKind::Radio,
+                ModuleKind::Radar
+            ]
+        );
+        assert_eq!(modules.effect(ModuleKind::Drill), 0.0);
+    }
+
+    #[test]
+    fn repair_is_capped_at_perfect_condition() {
+        let mut inventory = Inventory::with_items(1_000, 1_000, &[(Item::SpareParts, 10)]).unwrap();
+        let mut modules = with_conditions([1.0, 1.0, 0.9, 1.0, 1.0, 1.0, 1.0]);
+        assert_eq!(
+            modules.repair(ModuleKind::Engines, &mut inventory),
+            Err(RepairError::NothingToRepair)
+        );
+        assert_eq!(inventory.count(Item::SpareParts), 10);
+
+        assert_eq!(modules.repair(ModuleKind::Drill, &mut inventory), Ok(()));
+        assert_eq!(modules.get(ModuleKind::Drill).condition, 1.0);
+        assert_eq!(inventory.count(Item::SpareParts), 8);
+        assert_eq!(
+            modules.repair(ModuleKind::Drill, &mut inventory),
+            Err(RepairError::NothingToRepair)
+        );
+    }
+
+    #[test]
+    fn platform_stands_still_without_engines_or_tracks() {
+        let broken = BROKEN_CONDITION / 2.0;
+        assert!(with_conditions([1.0, 1.0, 1.0, 1.0, 1.0, 1.0, 1.0]).map_speed() > 0.0);
+        assert_eq!(
+            with_conditions([broken, 1.0, 1.0, 1.0, 1.0, 1.0, 1.0]).map_speed(),
+            0.0
+        );
+        assert_eq!(
+            with_conditions([1.0, broken, 1.0, 1.0, 1.0, 1.0, 1.0]).map_speed(),
+            0.0
+        );
+    }
+}
@@ -0,0 +1,76 @@
+use std::collections::VecDeque;
+
+use super::{
+    ui::{text_style, RADIO_TEXT_COLOR},
+    FontAssets,
+};
+use bevy::prelude::*;
+
+/// How many of the latest messages are shown
+const RADIO_LOG_LINES: usize = 5;
+
+pub struct RadioPlugin;
+
+impl Plugin for RadioPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<RadioMessage>()
+            .add_startup_system(spawn_radio_log)
+            .add_system(show_radio_messages);
+    }
+}
+
+/// Message received over the radio
+#[derive(Debug, Clone)]
+pub struct RadioMessage {
+    pub from: String,
+    pub text: String,
+}
+
+/// Text in the corner of the screen with the latest radio messages
+#[derive(Component, Default)]
+struct RadioLog {
+    lines: VecDeque<String>,
+}
+
+fn spawn_radio_log(mut commands: Commands, fonts: Res<FontAssets>) {
+    commands.spawn((
+        RadioLog::default(),
+        TextBundle::from_section(
+            "",
+            TextStyle {
+                color: RADIO_TEXT_COLOR,
+                ..text_style(&fonts)
+            },
+        )
+        .with_style(Style {
+            position_type: PositionType::Absolute,
+            position: UiRect {
+                left: Val::Px(10.0),
+                bottom: Val::Px(10.0),
+                ..default()
+            },
+            ..default()
+        }),
+    ));
+}
+
+fn show_radio_messages(
+    mut messages: EventReader<RadioMessage>,
+    mut radio_log: Query<(&mut RadioLog, &mut Text)>,
+) {
+    let (mut radio_log, mut text) = radio_log.single_mut();
+    let mut received = false;
+    for message in messages.iter() {
+        info!("Radio, {}: {}", message.from, message.text);
+        radio_log
+            .lines
+            .push_back(format!("{}: {}", message.from, message.text));
+        if radio_log.lines.len() > RADIO_LOG_LINES {
+            radio_log.lines.pop_front();
+        }
+        received = true;
+    }
+    if received {
+        text.sections[0].value = Vec::from(radio_log.lines.clone()).join("\n");
+    }
+}
@@ -0,0 +1,349 @@
+use super::{
+    chunk_management::{ChunkLoader, GeneratedChunks},
+    combat::{Encounter, Weapon},
+    inventory::Wallet,
+    movement::MapMovement,
+    navigation::{hex_distance, plan_route, RouteOrders},
+    terrain::TerrainMap,
+    world_seed::{SeedPurpose, WorldSeed},
+    ChartRange, Faction, MapPos, MiningPlatform, MovementConstraints, Npc,
+};
+use bevy::prelude::*;
+use bevy_ecs_tilemap::helpers::hex_grid::offset::RowEvenPos;
+use rand::prelude::*;
+use rand_chacha::ChaCha8Rng;
+use serde::{Deserialize, Serialize};
+
+/// Seconds between raids, the first one comes after the longest wait
+const MIN_RAID_INTERVAL: f32 = 240.0;
+const MAX_RAID_INTERVAL: f32 = 480.0;
+/// Raiders show up this many tiles beyond what the platform can see
+const SPAWN_MIN_BEYOND_SIGHT: u32 = 3;
+const SPAWN_MAX_BEYOND_SIGHT: u32 = 10;
+/// Tiles per second, raider cars are faster than the platform
+const RAIDER_MAP_SPEED: f32 = 0.9;
+/// Seconds between route updates while chasing the platform
+const REPLAN_INTERVAL: f32 = 2.0;
+/// Raiders give up once they are this far from the platform, in tiles
+const GIVE_UP_DISTANCE: u32 = 40;
+/// Fight starts when raiders are this close to the platform, in tiles
+const CONTACT_DISTANCE: u32 = 1;
+/// Raiders that run away head this far from the platform, in tiles
+const FLEE_DISTANCE: i32 = 45;
+/// People in every raider car that try to get on board
+pub const BOARDERS_PER_CAR: u32 = 3;
+
+const GANG_NAMES: [&str; 6] = [
+    "The Rust Jackals",
+    "Scrapfang's crew",
+    "The Glass Eaters",
+    "The Dust Wolves",
+    "Red Axle gang",
+    "The Burned Ones",
+];
+
+pub struct RaidersPlugin;
+
+impl Plugin for RaidersPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<RaidSchedule>()
+            .add_system(spawn_raiders)
+            .add_system(pursue.after(spawn_raiders))
+            .add_system(flee.after(pursue));
+    }
+}
+
+/// When the next raid comes, raids are numbered so that each gets its own random stream
+#[derive(Resource, Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct RaidSchedule {
+    pub raids: u64,
+    /// Seconds until the next raid
+    pub next_raid: f32,
+}
+
+impl Default for RaidSchedule {
+    fn default() -> Self {
+        Self {
+            raids: 0,
+            next_raid: MAX_RAID_INTERVAL,
+        }
+    }
+}
+
+/// Car held together by scrap and hope
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RaiderCar {
+    pub hp: u32,
+    pub max_hp: u32,
+    pub weapon: Weapon,
+}
+
+impl RaiderCar {
+    pub fn is_destroyed(&self) -> bool {
+        self.hp == 0
+    }
+}
+
+/// Group of raider cars that travels and fights together
+#[derive(Component, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RaiderGang {
+    pub name: String,
+    pub cars: Vec<RaiderCar>,
+    /// Seed for how fights go
+    pub seed: u64,
+    /// Set after a fight, the gang doesn't come back
+    pub fleeing: bool,
+    /// Seconds since the route was last updated
+    #[serde(skip)]
+    since_replan: f32,
+}
+
+impl RaiderGang {
+    /// Cars that are not destroyed
+    pub fn driving(&self) -> u32 {
+        self.cars.iter().filter(|car| !car.is_destroyed()).count() as u32
+    }
+}
+
+pub fn generate_gang(rng: &mut impl Rng) -> (RaiderGang, Wallet) {
+    let name = GANG_NAMES.choose(rng).unwrap().to_string();
+    let cars = (0..rng.gen_range(2..=4))
+        .map(|_| {
+            let max_hp = rng.gen_range(30..=60);
+            let weapon = match rng.gen_range(0..100) {
+                0..=59 => Weapon::Rifles,
+                60..=94 => Weapon::MountedGun,
+                _ => Weapon::Cannon,
+            };
+            RaiderCar {
+                hp: max_hp,
+                max_hp,
+                weapon,
+            }
+        })
+        .collect();
+    let gang = RaiderGang {
+        name,
+        cars,
+        seed: rng.gen(),
+        fleeing: false,
+        since_replan: REPLAN_INTERVAL,
+    };
+    (gang, Wallet(rng.gen_range(20..=150)))
+}
+
+/// Components of a raider gang, also used when loading a save
+pub fn raider_bundle(map_pos: MapPos, gang: RaiderGang, wallet: Wallet) -> impl Bundle {
+    (
+        map_pos,
+        MapMovement {
+            speed: RAIDER_MAP_SPEED,
+            constraints: MovementConstraints::Free,
+            halt: true,
+        },
+        RouteOrders::default(),
+        gang,
+        wallet,
+        Npc,
+        ChunkLoader::NPC,
+        Faction::Hostile,
+    )
+}
+
+/// Sends a gang after the platform from out of its sight every now and then
+fn spawn_raiders(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut schedule: ResMut<RaidSchedule>,
+    world_seed: Res<WorldSeed>,
+    generated_chunks: Res<GeneratedChunks>,
+    platform: Query<(&MapPos, &ChartRange), With<MiningPlatform>>,
+    gangs: Query<(), With<RaiderGang>>,
+) {
+    // One gang at a time, the next raid is planned once the last one is gone
+    if !gangs.is_empty() {
+        return;
+    }
+    let Ok((platform_pos, chart_range)) = platform.get_single() else {
+        return;
+    };
+    schedule.next_raid -= time.delta_seconds();
+    if schedule.next_raid > 0.0 {
+        return;
+    }
+    let mut rng = ChaCha8Rng::from_seed(world_seed.world_seed(SeedPurpose::Raiders));
+    rng.set_stream(schedule.raids);
+    schedule.raids += 1;
+    schedule.next_raid = rng.gen_range(MIN_RAID_INTERVAL..=MAX_RAID_INTERVAL);
+
+    let terrain = TerrainMap::new(&world_seed, &generated_chunks);
+    let min_distance = chart_range.0 + SPAWN_MIN_BEYOND_SIGHT;
+    let max_distance = (chart_range.0 + SPAWN_MAX_BEYOND_SIGHT) as i32;
+    let pos = (0..100).find_map(|_| {
+        let pos = RowEvenPos {
+            q: platform_pos.pos.q + rng.gen_range(-max_distance..=max_distance),
+            r: platform_pos.pos.r + rng.gen_range(-max_distance..=max_distance),
+        };
+        let distance = hex_distance(pos, platform_pos.pos);
+        (distance >= min_distance
+            && distance <= max_distance as u32
+            && terrain
+                .properties_at(pos)
+                .is_passable(MovementConstraints::Free))
+        .then_some(pos)
+    });
+    // Nowhere to come from, the gang tries another time
+    let Some(pos) = pos else {
+        return;
+    };
+    let (gang, wallet) = generate_gang(&mut rng);
+    info!("{} set out after the platform", gang.name);
+    commands.spawn(raider_bundle(MapPos { pos, ..default() }, gang, wallet));
+}
+
+/// Raiders chase the platform and start a fight when they catch up
+fn pursue(
+    mut commands: Commands,
+    time: Res<Time>,
+    world_seed: Res<WorldSeed>,
+    generated_chunks: Res<GeneratedChunks>,
+    platform: Query<&MapPos, With<MiningPlatform>>,
+    mut gangs: Query<
+        (Entity, &MapPos, &mut RaiderGang, &mut RouteOrders),
+        (Without<Encounter>, Without<MiningPlatform>),
+    >,
+    encounters: Query<(), With<Encounter>>,
+) {
+    let Ok(platform_pos) = platform.get_single() else {
+        return;
+    };
+    let terrain = TerrainMap::new(&world_seed, &generated_chunks);
+    let mut fighting = !encounters.is_empty();
+    for (entity, map_pos, mut gang, mut orders) in gangs.iter_mut() {
+        if gang.fleeing {
+            continue;
+        }
+        let distance = hex_distance(map_pos.pos, platform_pos.pos);
+        if distance > GIVE_UP_DISTANCE {
+            info!("{} gave up the chase", gang.name);
+            commands.entity(entity).despawn_recursive();
+            continue;
+        }
+        if distance <= CONTACT_DISTANCE && !fighting {
+            orders.0.clear();
+            commands.entity(entity).insert(Encounter::default());
+            info!("{} attack the platform", gang.name);
+            fighting = true;
+            continue;
+        }
+        gang.since_replan += time.delta_seconds();
+        if gang.since_replan < REPLAN_INTERVAL {
+            continue;
+        }
+        gang.since_replan = 0.0;
+        // Raiders can't follow into a sand sea, they wait at its edge until the platform comes out
+        if let Some(route) = plan_route(
+            map_pos,
+            platform_pos.pos,
+            MovementConstraints::Free,
+            &terrain,
+        ) {
+            orders.0 = route.into();
+        }
+    }
+}
+
+/// Gangs that lost a fight or got what they wanted drive away and are gone once out of reach
+fn flee(
+    mut commands: Commands,
+    world_seed: Res<WorldSeed>,
+    generated_chunks: Res<GeneratedChunks>,
+    platform: Query<&MapPos, With<MiningPlatform>>,
+    mut gangs: Query<
+        (Entity, &MapPos, &RaiderGang, &mut RouteOrders),
+        (Without<Encounter>, Without<MiningPlatform>),
+    >,
+) {
+    let Ok(platform_pos) = platform.get_single() else {
+        return;
+    };
+    let terrain = TerrainMap::new(&world_seed, &generated_chunks);
+    for (entity, map_pos, gang, mut orders) in gangs.iter_mut() {
+        if !gang.fleeing || !orders.0.is_empty() {
+            continue;
+        }
+        if hex_distance(map_pos.pos, platform_pos.pos) > GIVE_UP_DISTANCE {
+            info!("{} are gone", gang.name);
+            commands.entity(entity).despawn_recursive();
+            continue;
+        }
+        let away = Vec2::new(
+            (map_pos.pos.q - platform_pos.pos.q) as f32,
+            (map_pos.pos.r - platform_pos.pos.r) as f32,
+        )
+        .try_normalize()
+        .unwrap_or(Vec2::X)
+            * FLEE_DISTANCE as f32;
+        let goal = RowEvenPos {
+            q: map_pos.pos.q + away.x as i32,
+            r: map_pos.pos.r + away.y as i32,
+        };
+        match plan_route(map_pos, goal, MovementConstraints::Free, &terrain) {
+            Some(route) => orders.0 = route.into(),
+            // Gone in the dust where the platform can't follow
+            None => {
+                info!("{} are gone", gang.name);
+                commands.entity(entity).despawn_recursive();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::test_world;
+
+    /// Gang and schedule after the raid that is due
+    fn raid(schedule: RaidSchedule) -> (RaidSchedule, MapPos, RaiderGang, Wallet) {
+        let world = test_world(1);
+        let mut app = App::new();
+        app.add_system(spawn_raiders)
+            .insert_resource(schedule)
+            .insert_resource(world.world_seed)
+            .insert_resource(world.generated_chunks)
+            .insert_resource(Time::default());
+        app.world
+            .spawn((MapPos::default(), ChartRange(5), MiningPlatform));
+        app.update();
+        let mut gangs = app.world.query::<(&MapPos, &RaiderGang, &Wallet)>();
+        let (map_pos, gang, wallet) = gangs.single(&app.world);
+        (
+            *app.world.resource::<RaidSchedule>(),
+            map_pos.clone(),
+            gang.clone(),
+            *wallet,
+        )
+    }
+
+    #[test]
+    fn same_raid_brings_the_same_gang() {
+        let schedule = RaidSchedule {
+            raids: 3,
+            next_raid: 0.0,
+        };
+        let (schedule_after, map_pos, gang, wallet) = raid(schedule);
+        let (schedule_again, map_pos_again, gang_again, wallet_again) = raid(schedule);
+        assert_eq!(schedule_after, schedule_again);
+        assert_eq!(schedule_after.raids, 4);
+        assert_eq!(map_pos.pos, map_pos_again.pos);
+        assert_eq!(gang, gang_again);
+        assert_eq!(wallet, wallet_again);
+
+        let (_, _, next_gang, _) = raid(RaidSchedule {
+            next_raid: 0.0,
+            ..schedule_after
+        });
+        assert_ne!(gang, next_gang);
+    }
+}
@@ -0,0 +1,669 @@
+#![allow(clippy::too_many_arguments)]
+
+use std::{error::Error, fs};
+
+use super::{
+    charting::Lookouts,
+    chunk_management::{
+        charted_tiles, generate_chunk, ChartedTiles, ChunkLoader, GeneratedChunks,
+        GeneratingChunks, LoadedChunks,
+    },
+    combat::{Hull, WeaponMounts},
+    deposits::{generate_deposits, ChunkDeposits},
+    environment::{Sandstorms, TimeOfDay},
+    inventory::{Inventory, Wallet},
+    mining::Mining,
+    movement::MapMovement,
+    navigation::RouteOrders,
+    platform_modules::PlatformModules,
+    raiders::{raider_bundle, RaidSchedule, RaiderGang},
+    traders::{trader_bundle, Trader},
+    villages::{village_bundle, Village, Villages},
+    world_seed::SeedDerivation,
+    Chunk, ChunkPos, Crew, Faction, MapPos, MiningPlatform, Npc, TileKind, TileVisibility,
+    WorldSeed,
+};
+use bevy::{app::AppExit, ecs::system::SystemParam, prelude::*};
+use bevy_ecs_tilemap::{
+    helpers::hex_grid::{
+        neighbors::{HexDirection, HexRowDirection},
+        offset::RowEvenPos,
+    },
+    prelude::*,
+};
+use ron::ser::PrettyConfig;
+use serde::{Deserialize, Serialize};
+
+const SAVE_PATH: &str = "quicksave.ron";
+
+pub struct SavePlugin;
+
+impl Plugin for SavePlugin {
+    fn build(&self, app: &mut App) {
+        // After chunk management is done for the frame, so that loading starts from a clean slate
+        app.add_system(save_game.in_base_set(CoreSet::PostUpdate))
+            .add_system(load_game.in_base_set(CoreSet::PostUpdate).after(save_game))
+            .add_system(quicksave_on_exit.in_base_set(CoreSet::Last));
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SavedMapPos {
+    pos: (i32, i32),
+    current_direction: u8,
+    target_direction: Option<u8>,
+    reverse: bool,
+    progress: f32,
+}
+
+fn direction_to_index(direction: HexRowDirection) -> u8 {
+    HexDirection::from(direction) as u8
+}
+
+fn index_to_direction(index: u8) -> HexRowDirection {
+    HexDirection::from(index as usize).into()
+}
+
+impl From<&MapPos> for SavedMapPos {
+    fn from(map_pos: &MapPos) -> Self {
+        Self {
+            pos: (map_pos.pos.q, map_pos.pos.r),
+            current_direction: direction_to_index(map_pos.current_direction),
+            target_direction: map_pos.target_direction.map(direction_to_index),
+            reverse: map_pos.reverse,
+            progress: map_pos.progress,
+        }
+    }
+}
+
+impl From<&SavedMapPos> for MapPos {
+    fn from(saved: &SavedMapPos) -> Self {
+        Self {
+            pos: RowEvenPos {
+                q: saved.pos.0,
+                r: saved.pos.1,
+            },
+            current_direction: index_to_direction(saved.current_direction),
+            target_direction: saved.target_direction.map(index_to_direction),
+            reverse: saved.reverse,
+            progress: saved.progress,
+        }
+    }
+}
+
+/// Chunk that differs from what the world seed generates
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SavedChunk {
+    pos: (i32, i32),
+    charted: ChartedTiles,
+    /// Only present if tiles were changed after generation
+    tiles: Option<[[TileKind; 32]; 32]>,
+    /// Only present if deposits were mined
+    #[serde(default)]
+    deposits: Option<ChunkDeposits>,
+}
+
+/// Npc that is neither a trader nor raiders
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SavedNpc {
+    map_pos: SavedMapPos,
+    faction: Faction,
+    movement: Option<MapMovement>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SavedTrader {
+    map_pos: SavedMapPos,
+    trader: Trader,
+    inventory: Inventory,
+    wallet: Wallet,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SavedRaiders {
+    map_pos: SavedMapPos,
+    gang: RaiderGang,
+    wallet: Wallet,
+}
+
+/// Village that was visited, with its economy
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SavedVillage {
+    pos: (i32, i32),
+    village: Village,
+    inventory: Inventory,
+    wallet: Wallet,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SaveData {
+    seed: [u8; 32],
+    #[serde(default = "legacy_derivation")]
+    seed_derivation: SeedDerivation,
+    platform: SavedMapPos,
+    #[serde(default = "crate::platform_inventory")]
+    platform_inventory: Inventory,
+    #[serde(default)]
+    platform_wallet: Wallet,
+    #[serde(default)]
+    platform_hull: Hull,
+    #[serde(default)]
+    platform_modules: PlatformModules,
+    #[serde(default)]
+    platform_weapons: WeaponMounts,
+    #[serde(default)]
+    platform_crew: Crew,
+    #[serde(default)]
+    platform_lookouts: Lookouts,
+    /// Npcs of saves from before they were saved with their components, only read
+    #[serde(default, skip_serializing)]
+    npcs: Vec<SavedMapPos>,
+    #[serde(default)]
+    other_npcs: Vec<SavedNpc>,
+    #[serde(default)]
+    traders: Vec<SavedTrader>,
+    #[serde(default)]
+    villages: Vec<SavedVillage>,
+    #[serde(default)]
+    raiders: Vec<SavedRaiders>,
+    #[serde(default)]
+    raid_schedule: RaidSchedule,
+    #[serde(default)]
+    time_of_day: TimeOfDay,
+    #[serde(default)]
+    sandstorms: Sandstorms,
+    chunks: Vec<SavedChunk>,
+}
+
+/// Saves from before the seed derivation was saved are of legacy worlds
+fn legacy_derivation() -> SeedDerivation {
+    SeedDerivation::Legacy
+}
+
+pub fn write_save(path: &str, save: &SaveData) -> Result<(), Box<dyn Error>> {
+    fs::write(
+        path,
+        ron::ser::to_string_pretty(save, PrettyConfig::default())?,
+    )?;
+    Ok(())
+}
+
+pub fn read_save(path: &str) -> Result<SaveData, Box<dyn Error>> {
+    Ok(ron::from_str(&fs::read_to_string(path)?)?)
+}
+
+/// Everything that goes into a save
+#[derive(SystemParam)]
+pub struct WorldState<'w, 's> {
+    world_seed: Res<'w, WorldSeed>,
+    generated_chunks: Res<'w, GeneratedChunks>,
+    raid_schedule: Res<'w, RaidSchedule>,
+    time_of_day: Res<'w, TimeOfDay>,
+    sandstorms: Res<'w, Sandstorms>,
+    platform: Query<
+        'w,
+        's,
+        (
+            &'static MapPos,
+            &'static Inventory,
+            &'static Wallet,
+            &'static Hull,
+            &'static PlatformModules,
+            &'static WeaponMounts,
+            &'static Crew,
+            &'static Lookouts,
+        ),
+        With<MiningPlatform>,
+    >,
+    npcs: Query<
+        'w,
+        's,
+        (
+            &'static MapPos,
+            &'static Faction,
+            Option<&'static MapMovement>,
+        ),
+        (With<Npc>, Without<Trader>, Without<RaiderGang>),
+    >,
+    traders: Query<
+        'w,
+        's,
+        (
+            &'static MapPos,
+            &'static Trader,
+            &'static Inventory,
+            &'static Wallet,
+        ),
+    >,
+    raiders: Query<'w, 's, (&'static MapPos, &'static RaiderGang, &'static Wallet)>,
+    villages: Query<
+        'w,
+        's,
+        (
+            &'static MapPos,
+            &'static Village,
+            &'static Inventory,
+            &'static Wallet,
+        ),
+    >,
+    chunks: Query<'w, 's, (&'static Chunk, &'static TileStorage)>,
+    tiles: Query<'w, 's, &'static TileVisibility>,
+}
+
+impl<'w, 's> WorldState<'w, 's> {
+    pub fn collect(&self) -> SaveData {
+        let mut charted = self.generated_chunks.charted.clone();
+        for (chunk, tile_storage) in self.chunks.iter() {
+            charted.insert(chunk.pos, charted_tiles(tile_storage, &self.tiles));
+        }
+        let mut chunks: Vec<SavedChunk> = self
+            .generated_chunks
+            .chunks
+            .iter()
+            .map(|(pos, tiles)| SavedChunk {
+                pos: (pos.x, pos.y),
+                charted: charted.remove(pos).unwrap_or_default(),
+                tiles: (*tiles != generate_chunk(&self.world_seed, *pos)).then_some(*tiles),
+                deposits: self
+                    .generated_chunks
+                    .deposits
+                    .get(pos)
+                    .and_then(|deposits| {
+                        (*deposits != generate_deposits(&self.world_seed, *pos, tiles))
+                            .then(|| deposits.clone())
+                    }),
+            })
+            .collect();
+        // Charted chunks that were not generated again since loading
+        chunks.extend(charted.into_iter().map(|(pos, charted)| SavedChunk {
+            pos: (pos.x, pos.y),
+            charted,
+            tiles: None,
+            deposits: None,
+        }));
+        chunks.retain(|chunk| {
+            !chunk.charted.is_empty() || chunk.tiles.is_some() || chunk.deposits.is_some()
+        });
+        chunks.sort_by_key(|chunk| chunk.pos);
+        let mut villages: Vec<SavedVillage> = self
+            .villages
+            .iter()
+            .map(|(map_pos, village, inventory, wallet)| SavedVillage {
+                pos: (map_pos.pos.q, map_pos.pos.r),
+                village: village.clone(),
+                inventory: inventory.clone(),
+                wallet: *wallet,
+            })
+            .collect();
+        villages.sort_by_key(|village| village.pos);
+        let (
+            platform_pos,
+            platform_inventory,
+            platform_wallet,
+            platform_hull,
+            platform_modules,
+            platform_weapons,
+            platform_crew,
+            platform_lookouts,
+        ) = self.platform.single();
+        SaveData {
+            seed: self.world_seed.seed,
+            seed_derivation: self.world_seed.derivation,
+            platform: platform_pos.into(),
+            platform_inventory: platform_inventory.clone(),
+            platform_wallet: *platform_wallet,
+            platform_hull: *platform_hull,
+            platform_modules: platform_modules.clone(),
+            platform_weapons: platform_weapons.clone(),
+            platform_crew: *platform_crew,
+            platform_lookouts: *platform_lookouts,
+            npcs: Vec::new(),
+            other_npcs: self
+                .npcs
+                .iter()
+                .map(|(map_pos, faction, movement)| SavedNpc {
+                    map_pos: map_pos.into(),
+                    faction: *faction,
+                    movement: movement.copied(),
+                })
+                .collect(),
+            traders: self
+                .traders
+                .iter()
+                .map(|(map_pos, trader, inventory, wallet)| SavedTrader {
+                    map_pos: map_pos.into(),
+                    trader: trader.clone(),
+                    inventory: inventory.clone(),
+                    wallet: *wallet,
+                })
+                .collect(),
+            villages,
+            raiders: self
+                .raiders
+                .iter()
+                .map(|(map_pos, gang, wallet)| SavedRaiders {
+                    map_pos: map_pos.into(),
+                    gang: gang.clone(),
+                    wallet: *wallet,
+                })
+                .collect(),
+            raid_schedule: *self.raid_schedule,
+            time_of_day: *self.time_of_day,
+            sandstorms: *self.sandstorms,
+            chunks,
+        }
+    }
+}
+
+fn save(world_state: &WorldState) {
+    match write_save(SAVE_PATH, &world_state.collect()) {
+        Ok(()) => info!("Saved the game to {}", SAVE_PATH),
+        Err(e) => error!("Failed to save the game: {}", e),
+    }
+}
+
+fn save_game(input: Res<Input<KeyCode>>, world_state: WorldState) {
+    if input.just_pressed(KeyCode::F5) {
+        save(&world_state)
+    }
+}
+
+fn quicksave_on_exit(mut exit: EventReader<AppExit>, world_state: WorldState) {
+    if exit.iter().next().is_some() {
+        save(&world_state)
+    }
+}
+
+/// Components of an npc that is neither a trader nor raiders
+fn npc_bundle(map_pos: MapPos, faction: Faction) -> impl Bundle {
+    (map_pos, Npc, ChunkLoader::NPC, faction)
+}
+
+/// Everything a save is loaded into
+#[derive(SystemParam)]
+pub struct WorldStateMut<'w, 's> {
+    commands: Commands<'w, 's>,
+    world_seed: ResMut<'w, WorldSeed>,
+    generated_chunks: ResMut<'w, GeneratedChunks>,
+    loaded_chunks: ResMut<'w, LoadedChunks>,
+    generating_chunks: ResMut<'w, GeneratingChunks>,
+    chunks: Query<'w, 's, Entity, With<Chunk>>,
+    platform: Query<
+        'w,
+        's,
+        (
+            &'static mut MapPos,
+            &'static mut MapMovement,
+            &'static mut Mining,
+            &'static mut Inventory,
+            &'static mut Wallet,
+            &'static mut Hull,
+            &'static mut PlatformModules,
+            &'static mut WeaponMounts,
+            &'static mut Crew,
+            &'static mut Lookouts,
+            Option<&'static mut RouteOrders>,
+        ),
+        With<MiningPlatform>,
+    >,
+    npcs: Query<'w, 's, Entity, With<Npc>>,
+    villages: ResMut<'w, Villages>,
+    village_entities: Query<'w, 's, Entity, With<Village>>,
+    raid_schedule: ResMut<'w, RaidSchedule>,
+    time_of_day: ResMut<'w, TimeOfDay>,
+    sandstorms: ResMut<'w, Sandstorms>,
+}
+
+impl<'w, 's> WorldStateMut<'w, 's> {
+    pub fn apply(&mut self, save: &SaveData) {
+        *self.world_seed = WorldSeed {
+            seed: save.seed,
+            derivation: save.seed_derivation,
+        };
+        let generated_chunks = self.generated_chunks.as_mut();
+        generated_chunks.chunks.clear();
+        generated_chunks.deposits.clear();
+        generated_chunks.charted.clear();
+        // Chunks of the old world that are still being generated are not needed anymore
+        self.generating_chunks.0.clear();
+        for saved_chunk in save.chunks.iter() {
+            let pos = ChunkPos::new(saved_chunk.pos.0, saved_chunk.pos.1);
+            if let Some(tiles) = saved_chunk.tiles {
+                generated_chunks.chunks.insert(pos, tiles);
+            }
+            if let Some(deposits) = &saved_chunk.deposits {
+                generated_chunks.deposits.insert(pos, deposits.clone());
+            }
+            generated_chunks.charted.insert(pos, saved_chunk.charted);
+        }
+
+        // Chunks get loaded again with the new data on the next frame
+        for chunk_entity in self.chunks.iter() {
+            self.commands.entity(chunk_entity).despawn_recursive();
+        }
+        self.loaded_chunks.clear();
+
+        let (
+            mut platform_pos,
+            mut platform_movement,
+            mut mining,
+            mut inventory,
+            mut wallet,
+            mut hull,
+            mut modules,
+            mut weapons,
+            mut crew,
+            mut lookouts,
+            route_orders,
+        ) = self.platform.single_mut();
+        *platform_pos = (&save.platform).into();
+        platform_movement.halt = true;
+        mining.stop();
+        *inventory = save.platform_inventory.clone();
+        *wallet = save.platform_wallet;
+        *hull = save.platform_hull;
+        *modules = save.platform_modules.clone();
+        *weapons = save.platform_weapons.clone();
+        *crew = save.platform_crew;
+        *lookouts = save.platform_lookouts;
+        if let Some(mut route_orders) = route_orders {
+            route_orders.0.clear();
+        }
+
+        for npc in self.npcs.iter() {
+            self.commands.entity(npc).despawn_recursive();
+        }
+        for npc_pos in save.npcs.iter() {
+            self.commands
+                .spawn(npc_bundle(npc_pos.into(), Faction::Neutral));
+        }
+        for saved_npc in save.other_npcs.iter() {
+            let mut npc = self
+                .commands
+                .spawn(npc_bundle((&saved_npc.map_pos).into(), saved_npc.faction));
+            if let Some(movement) = saved_npc.movement {
+                npc.insert(movement);
+            }
+        }
+        for saved_trader in save.traders.iter() {
+            self.commands.spawn(trader_bundle(
+                (&saved_trader.map_pos).into(),
+                saved_trader.trader.clone(),
+                saved_trader.inventory.clone(),
+                saved_trader.wallet,
+            ));
+        }
+        for saved_raiders in save.raiders.iter() {
+            self.commands.spawn(raider_bundle(
+                (&saved_raiders.map_pos).into(),
+                saved_raiders.gang.clone(),
+                saved_raiders.wallet,
+            ));
+        }
+        *self.raid_schedule = save.raid_schedule;
+        *self.time_of_day = save.time_of_day;
+        *self.sandstorms = save.sandstorms;
+        for village in self.village_entities.iter() {
+            self.commands.entity(village).despawn_recursive();
+        }
+        self.villages.0.clear();
+        for saved_village in save.villages.iter() {
+            let pos = RowEvenPos {
+                q: saved_village.pos.0,
+                r: saved_village.pos.1,
+            };
+            let entity = self
+                .commands
+                .spawn(village_bundle(
+                    pos,
+                    saved_village.village.clone(),
+                    saved_village.inventory.clone(),
+                    saved_village.wallet,
+                ))
+                .id();
+            self.villages.0.insert(saved_village.pos, entity);
+        }
+    }
+}
+
+fn load_game(input: Res<Input<KeyCode>>, mut world_state: WorldStateMut) {
+    if !input.just_pressed(KeyCode::F9) {
+        return;
+    }
+    let save = match read_save(SAVE_PATH) {
+        Ok(save) => save,
+        Err(e) => {
+            error!("Failed to load the game: {}", e);
+            return;
+        }
+    };
+    world_state.apply(&save);
+    info!("Loaded the game from {}", SAVE_PATH);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        chunk_management::ChartedTiles, combat::Weapon, inventory::Item, raiders::generate_gang,
+        test_support, traders::generate_trader, villages::generate_village, MovementConstraints,
+    };
+    use bevy::ecs::system::SystemState;
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+
+    fn test_world() -> World {
+        let mut world = World::new();
+        let test_support::TestWorld {
+            world_seed,
+            mut generated_chunks,
+        } = test_support::test_world(0);
+        let mut charted = ChartedTiles::default();
+        charted.set(TilePos { x: 3, y: 4 }, true);
+        generated_chunks
+            .charted
+            .insert(ChunkPos::new(2, -1), charted);
+
+        let mut rng = ChaCha8Rng::seed_from_u64(1);
+        let mut inventory = Inventory::new(1_000, 1_000);
+        inventory.add(Item::Fuel, 50).unwrap();
+        world.spawn((
+            MapPos {
+                pos: RowEvenPos { q: 3, r: -2 },
+                progress: 0.25,
+                ..default()
+            },
+            MapMovement {
+                speed: 1.0,
+                constraints: MovementConstraints::Platform,
+                halt: true,
+            },
+            Mining::default(),
+            inventory,
+            Wallet(321),
+            Hull::default(),
+            PlatformModules::default(),
+            WeaponMounts(vec![None, Some(Weapon::Cannon)]),
+            Crew(5),
+            Lookouts(2),
+            MiningPlatform,
+        ));
+        let (trader, inventory, wallet) = generate_trader("Old Haskir", &mut rng);
+        world.spawn(trader_bundle(
+            MapPos {
+                pos: RowEvenPos { q: -5, r: 6 },
+                ..default()
+            },
+            trader,
+            inventory,
+            wallet,
+        ));
+        let (gang, wallet) = generate_gang(&mut rng);
+        world.spawn(raider_bundle(
+            MapPos {
+                pos: RowEvenPos { q: 10, r: 1 },
+                ..default()
+            },
+            gang,
+            wallet,
+        ));
+        world.spawn(npc_bundle(
+            MapPos {
+                pos: RowEvenPos { q: 0, r: 9 },
+                ..default()
+            },
+            Faction::Neutral,
+        ));
+        let village_pos = RowEvenPos { q: 4, r: 4 };
+        let (village, inventory, wallet) = generate_village(&world_seed, village_pos);
+        let village_entity = world
+            .spawn(village_bundle(village_pos, village, inventory, wallet))
+            .id();
+        let mut villages = Villages::default();
+        villages
+            .0
+            .insert((village_pos.q, village_pos.r), village_entity);
+
+        world.insert_resource(world_seed);
+        world.insert_resource(generated_chunks);
+        world.insert_resource(villages);
+        world.insert_resource(TimeOfDay {
+            day: 3,
+            seconds: 120.0,
+        });
+        world.init_resource::<LoadedChunks>();
+        world.init_resource::<GeneratingChunks>();
+        world.init_resource::<RaidSchedule>();
+        world.init_resource::<Sandstorms>();
+        world
+    }
+
+    fn collect(world: &mut World) -> SaveData {
+        SystemState::<WorldState>::new(world).get(world).collect()
+    }
+
+    #[test]
+    fn save_round_trip() {
+        let mut world = test_world();
+        let serialized = ron::to_string(&collect(&mut world)).unwrap();
+        let save: SaveData = ron::from_str(&serialized).unwrap();
+
+        // Play on a bit before loading
+        for mut wallet in world.query::<&mut Wallet>().iter_mut(&mut world) {
+            wallet.0 += 10;
+        }
+        for mut map_pos in world.query::<&mut MapPos>().iter_mut(&mut world) {
+            map_pos.pos.q += 1;
+        }
+        world.resource_mut::<GeneratedChunks>().charted.clear();
+        world.resource_mut::<TimeOfDay>().day += 1;
+
+        let mut state = SystemState::<WorldStateMut>::new(&mut world);
+        state.get_mut(&mut world).apply(&save);
+        state.apply(&mut world);
+
+        let loaded = collect(&mut world);
+        assert_eq!(loaded, save);
+        assert_eq!(ron::to_string(&loaded).unwrap(), serialized);
+    }
+}
@@ -0,0 +1,225 @@
+use super::{
+    chunk_management::{chunk_and_local_from_global, GeneratedChunks, TILEMAP_GRID_SIZE},
+    world_seed::{SeedPurpose, WorldSeed},
+    MovementConstraints, TileKind,
+};
+use bevy::prelude::*;
+use bevy_ecs_tilemap::helpers::hex_grid::offset::RowEvenPos;
+
+// Noise scales, in tiles
+const ELEVATION_SCALE: f32 = 24.0;
+const CANYON_SCALE: f32 = 40.0;
+const SAND_SCALE: f32 = 12.0;
+
+// Thresholds for picking a tile kind, tuned by looking at generated maps
+const CANYON_THRESHOLD: f32 = 0.96;
+const CANYON_MIN_ELEVATION: f32 = 0.4;
+const PLATEAU_ELEVATION: f32 = 0.62;
+const SALT_FLAT_ELEVATION: f32 = 0.36;
+const SAND_SEA_SAND: f32 = 0.6;
+const DUNES_SAND: f32 = 0.5;
+
+/// How a kind of terrain affects vehicles moving through it
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TerrainProperties {
+    /// Multiplier for vehicle speed, at most 1.0
+    pub speed: f32,
+    /// Fuel used to cross one tile
+    pub fuel_cost: u32,
+    /// Whether the tracked platform can enter
+    pub platform_passable: bool,
+    /// Whether wheeled vehicles that move freely can enter
+    pub free_passable: bool,
+}
+
+impl TerrainProperties {
+    pub fn is_passable(&self, constraints: MovementConstraints) -> bool {
+        match constraints {
+            MovementConstraints::Free => self.free_passable,
+            MovementConstraints::Platform => self.platform_passable,
+        }
+    }
+}
+
+impl TileKind {
+    pub fn properties(self) -> TerrainProperties {
+        let (speed, fuel_cost, platform_passable, free_passable) = match self {
+            Self::Empty | Self::Village => (1.0, 1, true, true),
+            Self::SaltFlat => (1.0, 1, true, true),
+            Self::Dunes => (0.6, 2, true, true),
+            Self::RockyPlateau => (0.5, 3, true, true),
+            // Too steep for the platform, cars can drive down and up again
+            Self::DriedCanyon => (0.7, 2, false, true),
+            // Cars sink into the loose sand, tracks keep the platform afloat
+            Self::SandSea => (0.4, 4, true, false),
+        };
+        TerrainProperties {
+            speed,
+            fuel_cost,
+            platform_passable,
+            free_passable,
+        }
+    }
+}
+
+/// Separate noise fields that terrain is made from
+#[derive(Debug, Clone, Copy)]
+enum NoiseLayer {
+    Elevation = 1,
+    Canyons = 2,
+    Sand = 3,
+}
+
+/// Seeded value noise that spans the whole world. It is sampled at global tile positions, so
+/// terrain continues seamlessly across chunk borders.
+pub struct TerrainNoise {
+    key: u64,
+}
+
+/// SplitMix64 finalizer
+fn mix(mut z: u64) -> u64 {
+    z = z.wrapping_add(0x9E3779B97F4A7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+    z ^ (z >> 31)
+}
+
+impl TerrainNoise {
+    pub fn new(world_seed: &WorldSeed) -> Self {
+        let seed = world_seed.world_seed(SeedPurpose::TerrainNoise);
+        Self {
+            key: u64::from_le_bytes(seed[..8].try_into().unwrap()),
+        }
+    }
+
+    /// Random value in 0.0..1.0 at a point of the noise lattice
+    fn lattice(&self, layer: NoiseLayer, octave: u32, x: i32, y: i32) -> f32 {
+        let mut hash = mix(self.key ^ layer as u64);
+        hash = mix(hash ^ octave as u64);
+        hash = mix(hash ^ x as u32 as u64);
+        hash = mix(hash ^ y as u32 as u64);
+        (hash >> 40) as f32 / (1 << 24) as f32
+    }
+
+    fn value_noise(&self, layer: NoiseLayer, octave: u32, pos: Vec2) -> f32 {
+        let cell = pos.floor();
+        let (x, y) = (cell.x as i32, cell.y as i32);
+        let fraction = pos - cell;
+        let smooth = fraction * fraction * (Vec2::splat(3.0) - 2.0 * fraction);
+        let bottom = self.lattice(layer, octave, x, y)
+            + (self.lattice(layer, octave, x + 1, y) - self.lattice(layer, octave, x, y))
+                * smooth.x;
+        let top = self.lattice(layer, octave, x, y + 1)
+            + (self.lattice(layer, octave, x + 1, y + 1) - self.lattice(layer, octave, x, y + 1))
+                * smooth.x;
+        bottom + (top - bottom) * smooth.y
+    }
+
+    /// Several octaves of value noise, in 0.0..1.0
+    fn fractal_noise(&self, layer: NoiseLayer, pos: Vec2, octaves: u32) -> f32 {
+        let mut value = 0.0;
+        let mut amplitude = 1.0;
+        let mut total_amplitude = 0.0;
+        for octave in 0..octaves {
+            value += self.value_noise(layer, octave, pos * (1 << octave) as f32) * amplitude;
+            total_amplitude += amplitude;
+            amplitude *= 0.5;
+        }
+        value / total_amplitude
+    }
+
+    pub fn terrain_at(&self, pos: RowEvenPos) -> TileKind {
+        // Roughly one unit per tile, without the distortion of offset coordinates
+        let world_pos = pos.center_in_world(&TILEMAP_GRID_SIZE) / TILEMAP_GRID_SIZE.y;
+        let elevation = self.fractal_noise(NoiseLayer::Elevation, world_pos / ELEVATION_SCALE, 4);
+        // Ridges of the noise form long winding lines
+        let canyon = 1.0
+            - (self.fractal_noise(NoiseLayer::Canyons, world_pos / CANYON_SCALE, 3) * 2.0 - 1.0)
+                .abs();
+        let sand = self.fractal_noise(NoiseLayer::Sand, world_pos / SAND_SCALE, 3);
+        if canyon > CANYON_THRESHOLD && elevation > CANYON_MIN_ELEVATION {
+            TileKind::DriedCanyon
+        } else if elevation > PLATEAU_ELEVATION {
+            TileKind::RockyPlateau
+        } else if elevation < SALT_FLAT_ELEVATION {
+            TileKind::SaltFlat
+        } else if sand > SAND_SEA_SAND {
+            TileKind::SandSea
+        } else if sand > DUNES_SAND {
+            TileKind::Dunes
+        } else {
+            TileKind::Empty
+        }
+    }
+}
+
+/// Tile kinds anywhere in the world: generated chunks are used where they exist, so that changes to
+/// them are seen, and terrain noise elsewhere
+pub struct TerrainMap<'a> {
+    generated_chunks: &'a GeneratedChunks,
+    /// Worlds without terrain noise are open sand outside of generated chunks
+    noise: Option<TerrainNoise>,
+}
+
+impl<'a> TerrainMap<'a> {
+    pub fn new(world_seed: &WorldSeed, generated_chunks: &'a GeneratedChunks) -> Self {
+        Self {
+            generated_chunks,
+            noise: world_seed
+                .derivation
+                .has_terrain_noise()
+                .then(|| TerrainNoise::new(world_seed)),
+        }
+    }
+
+    /// Tile kind at a global position. Villages are only known in generated chunks, but they
+    /// don't differ from open sand for movement.
+    pub fn kind_at(&self, pos: RowEvenPos) -> TileKind {
+        let (chunk_pos, tile_pos) = chunk_and_local_from_global(pos);
+        match self.generated_chunks.chunks.get(&chunk_pos) {
+            Some(tiles) => tiles[tile_pos.x as usize][tile_pos.y as usize],
+            None => self
+                .noise
+                .as_ref()
+                .map_or(TileKind::Empty, |noise| noise.terrain_at(pos)),
+        }
+    }
+
+    pub fn properties_at(&self, pos: RowEvenPos) -> TerrainProperties {
+        self.kind_at(pos).properties()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{chunk_management::TILEMAP_CHUNK_SIZE, test_support::test_seed};
+
+    #[test]
+    fn noise_is_continuous_across_chunk_borders() {
+        let noise = TerrainNoise::new(&test_seed());
+        let in_world =
+            |q, r| RowEvenPos { q, r }.center_in_world(&TILEMAP_GRID_SIZE) / TILEMAP_GRID_SIZE.y;
+        let last = TILEMAP_CHUNK_SIZE.x as i32 - 1;
+        // Last tile of a chunk and the first of the next one, along both axes
+        let borders = [
+            (in_world(last, 7), in_world(last + 1, 7)),
+            (in_world(-1, 20), in_world(0, 20)),
+            (in_world(7, last), in_world(7, last + 1)),
+            (in_world(20, -1), in_world(20, 0)),
+        ];
+        for (inside, outside) in borders {
+            let border = (inside + outside) / 2.0;
+            let step = (outside - inside).normalize() * 0.0001;
+            for (layer, scale) in [
+                (NoiseLayer::Elevation, ELEVATION_SCALE),
+                (NoiseLayer::Canyons, CANYON_SCALE),
+                (NoiseLayer::Sand, SAND_SCALE),
+            ] {
+                let before = noise.fractal_noise(layer, (border - step) / scale, 4);
+                let after = noise.fractal_noise(layer, (border + step) / scale, 4);
+                assert!((before - after).abs() < 0.001, "{:?} jumps", layer);
+            }
+        }
+    }
+}
@@ -0,0 +1,36 @@
+//! Fixtures shared by the tests of several modules
+
+use super::{
+    chunk_management::GeneratedChunks,
+    terrain::TerrainMap,
+    world_seed::{SeedDerivation, WorldSeed},
+    TileKind,
+};
+
+/// Open sand around the origin. Tiles can be changed with [`GeneratedChunks::set_kind`] before
+/// the terrain is looked at.
+pub(crate) struct TestWorld {
+    pub world_seed: WorldSeed,
+    pub generated_chunks: GeneratedChunks,
+}
+
+impl TestWorld {
+    pub fn terrain(&self) -> TerrainMap<'_> {
+        TerrainMap::new(&self.world_seed, &self.generated_chunks)
+    }
+}
+
+pub(crate) fn test_seed() -> WorldSeed {
+    WorldSeed {
+        seed: [7; 32],
+        derivation: SeedDerivation::default(),
+    }
+}
+
+/// Test seed with the chunks within `radius` of the origin generated as empty tiles
+pub(crate) fn test_world(radius: i32) -> TestWorld {
+    TestWorld {
+        world_seed: test_seed(),
+        generated_chunks: GeneratedChunks::filled(TileKind::Empty, radius),
+    }
+}
@@ -0,0 +1,310 @@
+use super::{
+    economy::{trade, Market, TradeAction, TradePartner},
+    inventory::{Inventory, Item, Wallet},
+    navigation::hex_distance,
+    traders::Trader,
+    ui::{text_style, BUTTON_COLOR, HOVERED_BUTTON_COLOR, PANEL_COLOR},
+    villages::Village,
+    FontAssets, MapPos, MiningPlatform,
+};
+use bevy::prelude::*;
+
+/// Trade screen opens when the platform is this close to a trader or village, in tiles
+const MEETING_DISTANCE: u32 = 1;
+/// Amount traded per click while holding shift
+const BULK_AMOUNT: u32 = 10;
+
+pub struct TradeScreenPlugin;
+
+impl Plugin for TradeScreenPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system(meet_trade_partners)
+            .add_system(close_trade_screen.after(meet_trade_partners))
+            .add_system(trade_buttons.after(close_trade_screen))
+            .add_system(update_trade_screen.after(trade_buttons));
+    }
+}
+
+/// Root of the trade screen
+#[derive(Component)]
+struct TradeScreen {
+    partner: Entity,
+}
+
+/// Text with the money of both sides
+#[derive(Component)]
+struct TradeHeader;
+
+/// Text with prices and amounts of an item
+#[derive(Component)]
+struct TradeRow {
+    item: Item,
+}
+
+#[derive(Component, Clone, Copy)]
+struct TradeButton {
+    item: Item,
+    action: TradeAction,
+}
+
+/// Price lookup of a trader or a village
+fn market<'a>((trader, village): (Option<&'a Trader>, Option<&'a Village>)) -> &'a dyn Market {
+    match (trader, village) {
+        (Some(trader), _) => trader,
+        (_, Some(village)) => village,
+        (None, None) => unreachable!("Trade partners are traders or villages"),
+    }
+}
+
+fn spawn_button(parent: &mut ChildBuilder, fonts: &FontAssets, button: TradeButton, label: &str) {
+    parent
+        .spawn((
+            button,
+            ButtonBundle {
+                style: Style {
+                    margin: UiRect::left(Val::Px(6.0)),
+                    padding: UiRect::new(Val::Px(6.0), Val::Px(6.0), Val::Px(2.0), Val::Px(2.0)),
+                    ..default()
+                },
+                background_color: BUTTON_COLOR.into(),
+                ..default()
+            },
+        ))
+        .with_children(|button| {
+            button.spawn(TextBundle::from_section(label, text_style(fonts)));
+        });
+}
+
+fn spawn_trade_screen(
+    commands: &mut Commands,
+    fonts: &FontAssets,
+    entity: Entity,
+    market: &dyn Market,
+) {
+    commands
+        .spawn((
+            TradeScreen { partner: entity },
+            NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    position: UiRect {
+                        right: Val::Px(20.0),
+                        top: Val::Px(20.0),
+                        ..default()
+                    },
+                    flex_direction: FlexDirection::Column,
+                    padding: UiRect::all(Val::Px(10.0)),
+                    ..default()
+                },
+                background_color: PANEL_COLOR.into(),
+                ..default()
+            },
+        ))
+        .with_children(|screen| {
+            screen.spawn((
+                TradeHeader,
+                TextBundle::from_section("", text_style(fonts)).with_style(Style {
+                    margin: UiRect::bottom(Val::Px(8.0)),
+                    ..default()
+                }),
+            ));
+            for item in market.goods() {
+                screen
+                    .spawn(NodeBundle {
+                        style: Style {
+                            flex_direction: FlexDirection::Row,
+                            align_items: AlignItems::Center,
+                            margin: UiRect::bottom(Val::Px(4.0)),
+                            ..default()
+                        },
+                        ..default()
+                    })
+                    .with_children(|row| {
+                        row.spawn((
+                            TradeRow { item },
+                            TextBundle::from_section("", text_style(fonts)),
+                        ));
+                        spawn_button(
+                            row,
+                            fonts,
+                            TradeButton {
+                                item,
+                                action: TradeAction::Buy,
+                            },
+                            "Buy",
+                        );
+                        spawn_button(
+                            row,
+                            fonts,
+                            TradeButton {
+                                item,
+                                action: TradeAction::Sell,
+                            },
+                            "Sell",
+                        );
+                    });
+            }
+            screen.spawn(
+                TextBundle::from_section(
+                    format!("Shift: {} at a time, Esc: leave", BULK_AMOUNT),
+                    text_style(fonts),
+                )
+                .with_style(Style {
+                    margin: UiRect::top(Val::Px(8.0)),
+                    ..default()
+                }),
+            );
+        });
+}
+
+/// Opens the trade screen when the platform gets next to a trader or a village
+fn meet_trade_partners(
+    mut commands: Commands,
+    fonts: Res<FontAssets>,
+    platform: Query<&MapPos, With<MiningPlatform>>,
+    mut partners: Query<(
+        Entity,
+        &MapPos,
+        &mut TradePartner,
+        AnyOf<(&Trader, &Village)>,
+    )>,
+    screens: Query<(), With<TradeScreen>>,
+) {
+    let Ok(platform_pos) = platform.get_single() else {
+        return;
+    };
+    let mut screen_open = !screens.is_empty();
+    for (entity, map_pos, mut partner, market_of) in partners.iter_mut() {
+        if hex_distance(map_pos.pos, platform_pos.pos) > MEETING_DISTANCE {
+            if partner.met_platform {
+                partner.met_platform = false;
+            }
+            continue;
+        }
+        if partner.met_platform || screen_open {
+            continue;
+        }
+        partner.met_platform = true;
+        partner.trading = true;
+        screen_open = true;
+        let market = market(market_of);
+        spawn_trade_screen(&mut commands, &fonts, entity, market);
+        info!("Met {}", market.name());
+    }
+}
+
+/// Closes the trade screen on request or when the platform and the partner part ways
+fn close_trade_screen(
+    mut commands: Commands,
+    input: Res<Input<KeyCode>>,
+    screens: Query<(Entity, &TradeScreen)>,
+    mut partners: Query<&mut TradePartner>,
+) {
+    for (screen_entity, screen) in screens.iter() {
+        let partner = partners.get_mut(screen.partner).ok();
+        let parted = !partner.as_ref().is_some_and(|partner| partner.met_platform);
+        if parted || input.just_pressed(KeyCode::Escape) {
+            if let Some(mut partner) = partner {
+                partner.trading = false;
+            }
+            commands.entity(screen_entity).despawn_recursive();
+        }
+    }
+}
+
+fn trade_buttons(
+    input: Res<Input<KeyCode>>,
+    mut buttons: Query<(&Interaction, &TradeButton, &mut BackgroundColor), Changed<Interaction>>,
+    screen: Query<&TradeScreen>,
+    platform: Query<Entity, With<MiningPlatform>>,
+    markets: Query<AnyOf<(&Trader, &Village)>>,
+    mut parties: Query<(&mut Inventory, &mut Wallet)>,
+) {
+    for (interaction, button, mut color) in buttons.iter_mut() {
+        match interaction {
+            Interaction::Clicked => {
+                *color = HOVERED_BUTTON_COLOR.into();
+                let Ok(screen) = screen.get_single() else {
+                    continue;
+                };
+                let Ok(market_of) = markets.get(screen.partner) else {
+                    continue;
+                };
+                let amount = if input.any_pressed([KeyCode::LShift, KeyCode::RShift]) {
+                    BULK_AMOUNT
+                } else {
+                    1
+                };
+                let Ok(platform) = platform.get_single() else {
+                    continue;
+                };
+                let Ok(
+                    [(mut player_inventory, mut player_wallet), (mut partner_inventory, mut partner_wallet)],
+                ) = parties.get_many_mut([platform, screen.partner])
+                else {
+                    continue;
+                };
+                if let Err(e) = trade(
+                    market(market_of),
+                    button.action,
+                    button.item,
+                    amount,
+                    (&mut player_inventory, &mut player_wallet),
+                    (&mut partner_inventory, &mut partner_wallet),
+                ) {
+                    warn!(
+                        "Can't {:?} {} {}: {}",
+                        button.action,
+                        amount,
+                        button.item.name(),
+                        e
+                    );
+                }
+            }
+            Interaction::Hovered => *color = HOVERED_BUTTON_COLOR.into(),
+            Interaction::None => *color = BUTTON_COLOR.into(),
+        }
+    }
+}
+
+fn update_trade_screen(
+    screen: Query<&TradeScreen>,
+    platform: Query<(&Inventory, &Wallet), With<MiningPlatform>>,
+    partners: Query<(AnyOf<(&Trader, &Village)>, &Inventory, &Wallet), Without<MiningPlatform>>,
+    mut header: Query<&mut Text, With<TradeHeader>>,
+    mut rows: Query<(&mut Text, &TradeRow), Without<TradeHeader>>,
+) {
+    let Ok(screen) = screen.get_single() else {
+        return;
+    };
+    let Ok((market_of, partner_inventory, partner_wallet)) = partners.get(screen.partner) else {
+        return;
+    };
+    let market = market(market_of);
+    let Ok((player_inventory, player_wallet)) = platform.get_single() else {
+        return;
+    };
+    header.single_mut().sections[0].value = format!(
+        "{}\nYour money: {}  Their money: {}\nCargo: {}/{} kg, {}/{} l",
+        market.name(),
+        player_wallet.0,
+        partner_wallet.0,
+        player_inventory.mass(),
+        player_inventory.max_mass(),
+        player_inventory.volume(),
+        player_inventory.max_volume()
+    );
+    for (mut text, row) in rows.iter_mut() {
+        let Some(prices) = market.prices(row.item, partner_inventory) else {
+            continue;
+        };
+        text.sections[0].value = format!(
+            "{:<12} buy {:>4} sell {:>4}  have {:>5} they have {:>5}",
+            row.item.name(),
+            prices.buy,
+            prices.sell,
+            player_inventory.count(row.item),
+            partner_inventory.count(row.item)
+        );
+    }
+}
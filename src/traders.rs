@@ -0,0 +1,327 @@
+use std::{collections::BTreeMap, f32::consts::PI};
+
+use super::{
+    chunk_management::{ChunkLoader, GeneratedChunks, TILEMAP_GRID_SIZE},
+    deposits::Ore,
+    economy::{Market, Prices, TradePartner},
+    inventory::{Inventory, Item, Wallet},
+    movement::MapMovement,
+    navigation::{hex_distance, plan_route, RouteOrders},
+    platform_modules::{ModuleKind, PlatformModules},
+    radio::RadioMessage,
+    terrain::TerrainMap,
+    villages::{Village, VISIT_DISTANCE},
+    world_seed::{SeedPurpose, WorldSeed},
+    Faction, MapPos, MiningPlatform, MovementConstraints, Npc,
+};
+use bevy::prelude::*;
+use bevy_ecs_tilemap::helpers::hex_grid::offset::RowEvenPos;
+use rand::prelude::*;
+use rand_chacha::ChaCha8Rng;
+use serde::{Deserialize, Serialize};
+
+const TRADER_COUNT: usize = 4;
+/// Traders start at most this far from the world origin, in tiles
+const TRADER_SPAWN_RADIUS: i32 = 40;
+/// Tiles per second
+const TRADER_MAP_SPEED: f32 = 0.8;
+/// How far a trader goes in one trip, in tiles
+const ROAM_DISTANCE: i32 = 20;
+/// Traders call the platform when it's this close and its radio is in perfect condition, in tiles
+const RADIO_RANGE: u32 = 25;
+/// Seconds between calls of one trader
+const BROADCAST_INTERVAL: f32 = 60.0;
+
+/// Cargo limits of a trader's car, in kilograms and liters
+const TRADER_MAX_CARGO_MASS: u32 = 4_000;
+const TRADER_MAX_CARGO_VOLUME: u32 = 3_000;
+
+/// Part of their asking price traders pay for goods
+const SELL_FACTOR: f32 = 0.7;
+/// What traders add to village prices when they pass them on
+const VILLAGE_PRICE_MARKUP: f32 = 1.2;
+
+const TRADER_NAMES: [&str; 8] = [
+    "Old Haskir",
+    "The Dune Sisters",
+    "Merrow's Caravan",
+    "Tamsin Two-Wheels",
+    "Rustbucket Joe",
+    "Ilka of the Wells",
+    "The Copper Brothers",
+    "Nine-Lives Oduya",
+];
+
+/// Goods traders deal in, with base price and the most a trader carries
+const GOODS: [(Item, u32, u32); 7] = [
+    (Item::Ore(Ore::Iron), 12, 20),
+    (Item::Ore(Ore::Copper), 18, 20),
+    (Item::Ore(Ore::Salt), 6, 40),
+    (Item::Scrap, 8, 20),
+    (Item::Fuel, 2, 400),
+    (Item::Water, 1, 300),
+    (Item::SpareParts, 40, 8),
+];
+
+pub struct TradersPlugin;
+
+impl Plugin for TradersPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_startup_system(spawn_traders)
+            .add_system(roam)
+            .add_system(broadcast)
+            .add_system(follow_village_prices);
+    }
+}
+
+/// Wandering trader that exchanges goods for money
+#[derive(Component, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Trader {
+    pub name: String,
+    pub prices: BTreeMap<Item, Prices>,
+    /// Seed for picking where to go
+    seed: u64,
+    /// Trips made so far, each trip gets its own random stream
+    trips: u64,
+    /// When the trader last called the platform, in seconds since start
+    #[serde(skip)]
+    last_broadcast: Option<f32>,
+    /// Village the trader last took prices from, so that it's only done once per visit
+    #[serde(skip)]
+    last_village: Option<Entity>,
+}
+
+impl Market for Trader {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Traders keep their prices however much they have
+    fn prices(&self, item: Item, _stock: &Inventory) -> Option<Prices> {
+        self.prices.get(&item).copied()
+    }
+
+    fn goods(&self) -> Vec<Item> {
+        self.prices.keys().copied().collect()
+    }
+}
+
+impl Trader {
+    fn broadcast_text(&self, trader_pos: RowEvenPos, platform_pos: RowEvenPos) -> String {
+        let goods: Vec<&str> = self.prices.keys().map(|item| item.name()).collect();
+        format!(
+            "Trading {} for fair prices. We are {} tiles {} of you.",
+            goods.join(", "),
+            hex_distance(trader_pos, platform_pos),
+            compass_direction(platform_pos, trader_pos)
+        )
+    }
+}
+
+/// Direction from one tile to another as it's said over the radio
+fn compass_direction(from: RowEvenPos, to: RowEvenPos) -> &'static str {
+    const DIRECTIONS: [&str; 8] = [
+        "east",
+        "north-east",
+        "north",
+        "north-west",
+        "west",
+        "south-west",
+        "south",
+        "south-east",
+    ];
+    let offset = to.center_in_world(&TILEMAP_GRID_SIZE) - from.center_in_world(&TILEMAP_GRID_SIZE);
+    let sector = (offset.y.atan2(offset.x) / (PI / 4.0)).round() as i32;
+    DIRECTIONS[sector.rem_euclid(8) as usize]
+}
+
+/// Trader with its goods and money
+pub fn generate_trader(name: &str, rng: &mut impl Rng) -> (Trader, Inventory, Wallet) {
+    let mut prices = BTreeMap::new();
+    let mut inventory = Inventory::new(TRADER_MAX_CARGO_MASS, TRADER_MAX_CARGO_VOLUME);
+    for (item, base_price, max_stock) in GOODS {
+        // Same amount of numbers is rolled for every item, so that a trader's goods don't shift
+        // each other's prices
+        let deals = rng.gen_bool(0.75);
+        let factor = rng.gen_range(0.8..1.3);
+        let stock = rng.gen_range(0..=max_stock);
+        if !deals {
+            continue;
+        }
+        let buy = ((base_price as f32 * factor).round() as u32).max(1);
+        let sell = (buy as f32 * SELL_FACTOR) as u32;
+        prices.insert(item, Prices { buy, sell });
+        let stock = stock.min(inventory.space_for(item));
+        inventory
+            .add(item, stock)
+            .expect("Stock is limited to what fits");
+    }
+    let trader = Trader {
+        name: name.to_string(),
+        prices,
+        seed: rng.gen(),
+        trips: 0,
+        last_broadcast: None,
+        last_village: None,
+    };
+    (trader, inventory, Wallet(rng.gen_range(500..=2000)))
+}
+
+/// Components of a trader, also used when loading a save
+pub fn trader_bundle(
+    map_pos: MapPos,
+    trader: Trader,
+    inventory: Inventory,
+    wallet: Wallet,
+) -> impl Bundle {
+    (
+        map_pos,
+        MapMovement {
+            speed: TRADER_MAP_SPEED,
+            constraints: MovementConstraints::Free,
+            halt: true,
+        },
+        RouteOrders::default(),
+        trader,
+        inventory,
+        wallet,
+        TradePartner::default(),
+        Npc,
+        ChunkLoader::NPC,
+        Faction::Neutral,
+    )
+}
+
+fn spawn_traders(
+    mut commands: Commands,
+    world_seed: Res<WorldSeed>,
+    generated_chunks: Res<GeneratedChunks>,
+) {
+    let terrain = TerrainMap::new(&world_seed, &generated_chunks);
+    let mut rng = ChaCha8Rng::from_seed(world_seed.world_seed(SeedPurpose::Traders));
+    let names: Vec<&str> = TRADER_NAMES
+        .choose_multiple(&mut rng, TRADER_COUNT)
+        .copied()
+        .collect();
+    for name in names {
+        let (trader, inventory, wallet) = generate_trader(name, &mut rng);
+        // Somewhere a car can stand
+        let pos = (0..100).find_map(|_| {
+            let pos = RowEvenPos {
+                q: rng.gen_range(-TRADER_SPAWN_RADIUS..=TRADER_SPAWN_RADIUS),
+                r: rng.gen_range(-TRADER_SPAWN_RADIUS..=TRADER_SPAWN_RADIUS),
+            };
+            terrain
+                .properties_at(pos)
+                .is_passable(MovementConstraints::Free)
+                .then_some(pos)
+        });
+        let Some(pos) = pos else {
+            warn!("Nowhere for {} to stand, the trader stays home", name);
+            continue;
+        };
+        commands.spawn(trader_bundle(
+            MapPos { pos, ..default() },
+            trader,
+            inventory,
+            wallet,
+        ));
+    }
+}
+
+/// Sends traders to random places nearby, one trip after another
+fn roam(
+    mut traders: Query<(&MapPos, &mut Trader, &TradePartner, &mut RouteOrders)>,
+    world_seed: Res<WorldSeed>,
+    generated_chunks: Res<GeneratedChunks>,
+) {
+    let terrain = TerrainMap::new(&world_seed, &generated_chunks);
+    for (map_pos, mut trader, partner, mut orders) in traders.iter_mut() {
+        if partner.trading {
+            if !orders.0.is_empty() {
+                orders.0.clear();
+            }
+            continue;
+        }
+        if !orders.0.is_empty() {
+            continue;
+        }
+        let mut rng = ChaCha8Rng::seed_from_u64(trader.seed);
+        rng.set_stream(trader.trips);
+        trader.trips += 1;
+        let goal = RowEvenPos {
+            q: map_pos.pos.q + rng.gen_range(-ROAM_DISTANCE..=ROAM_DISTANCE),
+            r: map_pos.pos.r + rng.gen_range(-ROAM_DISTANCE..=ROAM_DISTANCE),
+        };
+        // Unreachable goals are skipped, next trip is tried on the next frame
+        if let Some(route) = plan_route(map_pos, goal, MovementConstraints::Free, &terrain) {
+            orders.0 = route.into();
+        }
+    }
+}
+
+/// Traders call the platform over the radio when it's in range
+fn broadcast(
+    time: Res<Time>,
+    platform: Query<(&MapPos, &PlatformModules), With<MiningPlatform>>,
+    mut traders: Query<(&MapPos, &mut Trader, &TradePartner)>,
+    mut radio: EventWriter<RadioMessage>,
+) {
+    let Ok((platform_pos, modules)) = platform.get_single() else {
+        return;
+    };
+    // Calls only get through as far as the radio picks them up
+    let range = (RADIO_RANGE as f32 * modules.effect(ModuleKind::Radio)) as u32;
+    if range == 0 {
+        return;
+    }
+    let now = time.elapsed_seconds();
+    for (map_pos, mut trader, partner) in traders.iter_mut() {
+        if partner.trading
+            || hex_distance(map_pos.pos, platform_pos.pos) > range
+            || trader
+                .last_broadcast
+                .is_some_and(|last| now - last < BROADCAST_INTERVAL)
+        {
+            continue;
+        }
+        trader.last_broadcast = Some(now);
+        radio.send(RadioMessage {
+            from: trader.name.clone(),
+            text: trader.broadcast_text(map_pos.pos, platform_pos.pos),
+        });
+    }
+}
+
+/// Traders passing a village take its prices for the goods they deal in, with their markup
+fn follow_village_prices(
+    mut traders: Query<(&MapPos, &mut Trader)>,
+    villages: Query<(Entity, &MapPos, &Village, &Inventory)>,
+) {
+    for (map_pos, mut trader) in traders.iter_mut() {
+        let Some((entity, village, stock)) = villages
+            .iter()
+            .find(|(_, village_pos, ..)| {
+                hex_distance(village_pos.pos, map_pos.pos) <= VISIT_DISTANCE
+            })
+            .map(|(entity, _, village, stock)| (entity, village, stock))
+        else {
+            continue;
+        };
+        if trader.last_village == Some(entity) {
+            continue;
+        }
+        trader.last_village = Some(entity);
+        for (item, prices) in trader.prices.iter_mut() {
+            if let Some(village_prices) = village.prices(*item, stock) {
+                let buy =
+                    ((village_prices.buy as f32 * VILLAGE_PRICE_MARKUP).round() as u32).max(1);
+                *prices = Prices {
+                    buy,
+                    sell: (buy as f32 * SELL_FACTOR) as u32,
+                };
+            }
+        }
+        debug!("{} took prices from {}", trader.name, village.name);
+    }
+}
@@ -0,0 +1,19 @@
+use super::FontAssets;
+use bevy::prelude::*;
+
+/// Look shared by the panels and screens of the game
+pub const TEXT_COLOR: Color = Color::rgb(0.95, 0.9, 0.8);
+pub const PANEL_COLOR: Color = Color::rgba(0.12, 0.1, 0.07, 0.92);
+pub const BUTTON_COLOR: Color = Color::rgb(0.35, 0.28, 0.16);
+pub const HOVERED_BUTTON_COLOR: Color = Color::rgb(0.5, 0.4, 0.22);
+/// Text that stands out from the rest: what comes over the radio and how a fight goes
+pub const RADIO_TEXT_COLOR: Color = Color::rgb(0.6, 1.0, 0.6);
+pub const COMBAT_TEXT_COLOR: Color = Color::rgb(1.0, 0.6, 0.5);
+
+pub fn text_style(fonts: &FontAssets) -> TextStyle {
+    TextStyle {
+        font: fonts.ui.clone(),
+        font_size: 16.0,
+        color: TEXT_COLOR,
+    }
+}
@@ -0,0 +1,296 @@
+use std::collections::BTreeMap;
+
+use super::{
+    chunk_management::{chunk_and_local_from_global, GeneratedChunks, TILEMAP_CHUNK_SIZE},
+    deposits::Ore,
+    economy::{Market, Prices, TradePartner},
+    inventory::{Inventory, Item, Wallet},
+    navigation::hex_distance,
+    terrain::{TerrainMap, TerrainNoise},
+    traders::Trader,
+    world_seed::{SeedPurpose, WorldSeed},
+    MapPos, MiningPlatform, TileKind,
+};
+use bevy::{prelude::*, utils::HashMap};
+use bevy_ecs_tilemap::helpers::hex_grid::offset::RowEvenPos;
+use rand::prelude::*;
+use rand_chacha::ChaCha8Rng;
+use serde::{Deserialize, Serialize};
+
+/// A village is visited when the platform or a trader gets this close, in tiles
+pub const VISIT_DISTANCE: u32 = 1;
+/// Seconds between updates of village economies
+const ECONOMY_TICK: f32 = 10.0;
+/// Stock a village wants to keep, in minutes of what it makes or uses
+const TARGET_STOCK_MINUTES: f32 = 30.0;
+/// Villages stop making goods when they have this many times the stock they want
+const MAX_STOCK_FACTOR: f32 = 3.0;
+/// Bounds of how far prices go from the base price because of stock
+const MIN_PRICE_FACTOR: f32 = 0.3;
+const MAX_PRICE_FACTOR: f32 = 4.0;
+/// Largest change of the price drift in one tick, and its bounds
+const DRIFT_STEP: f32 = 0.03;
+const MIN_DRIFT: f32 = 0.75;
+const MAX_DRIFT: f32 = 1.33;
+/// Difference between what a village asks and pays, as a part of the price
+const PRICE_SPREAD: f32 = 0.15;
+/// Money a village makes per inhabitant per tick from trade that isn't simulated
+const INCOME_PER_INHABITANT: f32 = 0.05;
+/// Villages stop saving their income at this much money per inhabitant
+const MAX_SAVINGS_PER_INHABITANT: u32 = 20;
+
+/// Villages keep their goods in storehouses that are in practice never full
+const VILLAGE_MAX_STORAGE_MASS: u32 = 1_000_000;
+const VILLAGE_MAX_STORAGE_VOLUME: u32 = 1_000_000;
+
+/// Goods villages deal in, with base price and what 100 inhabitants make and use per minute
+const VILLAGE_GOODS: [(Item, u32, f32, f32); 7] = [
+    (Item::Ore(Ore::Iron), 12, 0.0, 1.0),
+    (Item::Ore(Ore::Copper), 18, 0.0, 0.6),
+    (Item::Ore(Ore::Salt), 6, 0.5, 1.5),
+    (Item::Scrap, 8, 0.5, 0.5),
+    (Item::Fuel, 2, 0.0, 10.0),
+    (Item::Water, 1, 20.0, 15.0),
+    (Item::SpareParts, 40, 0.0, 0.3),
+];
+
+const NAME_BEGINNINGS: [&str; 12] = [
+    "Al", "Bir", "Dar", "Ez", "Ghar", "Has", "Kel", "Mar", "Nef", "Qas", "Sul", "Zah",
+];
+const NAME_ENDINGS: [&str; 8] = ["abad", "a", "im", "un", "esh", "ir", "ah", "oum"];
+
+pub struct VillagesPlugin;
+
+impl Plugin for VillagesPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Villages>()
+            .add_system(visit_villages)
+            .add_system(run_village_economies);
+    }
+}
+
+/// Village entities by position. Villages only become entities when first visited, and stay
+/// entities from then on.
+#[derive(Resource, Debug, Default)]
+pub struct Villages(pub HashMap<(i32, i32), Entity>);
+
+/// Supply and demand of one good in a village
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Good {
+    /// Units made per minute
+    pub supply: f32,
+    /// Units used up per minute
+    pub demand: f32,
+    /// Price when the stock is what the village wants
+    base_price: u32,
+    /// Slow random change of the price, as a factor
+    drift: f32,
+    /// Part of a unit that was made or used up but isn't in the stock yet
+    pending: f32,
+}
+
+impl Good {
+    /// Stock the village wants to keep
+    fn target_stock(&self) -> f32 {
+        self.supply.max(self.demand).max(1.0) * TARGET_STOCK_MINUTES
+    }
+
+    /// Price without the spread, goes up as the stock runs low
+    fn price(&self, stock: u32) -> f32 {
+        let scarcity = (self.target_stock() / stock.max(1) as f32)
+            .sqrt()
+            .clamp(MIN_PRICE_FACTOR, MAX_PRICE_FACTOR);
+        self.base_price as f32 * scarcity * self.drift
+    }
+}
+
+/// Settlement that trades with the player and with traders
+#[derive(Component, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Village {
+    pub name: String,
+    pub population: u32,
+    pub goods: BTreeMap<Item, Good>,
+    /// Seed for the price drift
+    seed: u64,
+    /// Economy updates so far, each gets its own random stream
+    ticks: u64,
+    /// Seconds since the last economy update
+    #[serde(skip)]
+    since_tick: f32,
+}
+
+impl Market for Village {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn prices(&self, item: Item, stock: &Inventory) -> Option<Prices> {
+        let price = self.goods.get(&item)?.price(stock.count(item));
+        Some(Prices {
+            buy: ((price * (1.0 + PRICE_SPREAD)).ceil() as u32).max(1),
+            sell: (price * (1.0 - PRICE_SPREAD)) as u32,
+        })
+    }
+
+    fn goods(&self) -> Vec<Item> {
+        self.goods.keys().copied().collect()
+    }
+}
+
+impl Village {
+    /// Makes and uses up goods and lets prices drift
+    fn tick(&mut self, stock: &mut Inventory, wallet: &mut Wallet) {
+        let mut rng = ChaCha8Rng::seed_from_u64(self.seed);
+        rng.set_stream(self.ticks);
+        self.ticks += 1;
+        let minutes = ECONOMY_TICK / 60.0;
+        for (item, good) in self.goods.iter_mut() {
+            let step: f32 = rng.gen_range(-DRIFT_STEP..=DRIFT_STEP);
+            good.drift = (good.drift * step.exp()).clamp(MIN_DRIFT, MAX_DRIFT);
+
+            good.pending += (good.supply - good.demand) * minutes;
+            let have = stock.count(*item);
+            if good.pending >= 1.0 {
+                let room = (good.target_stock() * MAX_STOCK_FACTOR) as u32;
+                let amount = (good.pending as u32)
+                    .min(room.saturating_sub(have))
+                    .min(stock.space_for(*item));
+                stock
+                    .add(*item, amount)
+                    .expect("Amount is limited to what fits");
+            } else if good.pending <= -1.0 {
+                let amount = (-good.pending as u32).min(have);
+                stock
+                    .remove(*item, amount)
+                    .expect("Amount is limited to the stock");
+            }
+            // What can't be made or used up is lost
+            good.pending = good.pending.fract();
+        }
+        let savings = self.population * MAX_SAVINGS_PER_INHABITANT;
+        if wallet.0 < savings {
+            let income = (self.population as f32 * INCOME_PER_INHABITANT) as u32;
+            wallet.0 = (wallet.0 + income).min(savings);
+        }
+    }
+}
+
+fn village_name(rng: &mut impl Rng) -> String {
+    format!(
+        "{}{}",
+        NAME_BEGINNINGS.choose(rng).unwrap(),
+        NAME_ENDINGS.choose(rng).unwrap()
+    )
+}
+
+/// Village at a position with its stock and money, the same for the same world seed
+pub fn generate_village(world_seed: &WorldSeed, pos: RowEvenPos) -> (Village, Inventory, Wallet) {
+    let (chunk_pos, tile_pos) = chunk_and_local_from_global(pos);
+    let mut rng = world_seed.chunk_rng(chunk_pos, SeedPurpose::VillageEconomies);
+    rng.set_stream((tile_pos.y * TILEMAP_CHUNK_SIZE.x + tile_pos.x) as u64);
+    // Terrain the village was built on
+    let terrain = if world_seed.derivation.has_terrain_noise() {
+        TerrainNoise::new(world_seed).terrain_at(pos)
+    } else {
+        TileKind::Empty
+    };
+
+    let name = village_name(&mut rng);
+    let population = rng.gen_range(20..=300);
+    let mut goods = BTreeMap::new();
+    let mut stock = Inventory::new(VILLAGE_MAX_STORAGE_MASS, VILLAGE_MAX_STORAGE_VOLUME);
+    for (item, base_price, supply, demand) in VILLAGE_GOODS {
+        // Same amount of numbers is rolled for every good, so that goods don't shift each other
+        let supply_factor = rng.gen_range(0.5..1.5);
+        let demand_factor = rng.gen_range(0.5..1.5);
+        let stock_factor = rng.gen_range(0.5..1.5);
+        let terrain_factor = match (terrain, item) {
+            (TileKind::SaltFlat, Item::Ore(Ore::Salt)) => 6.0,
+            (TileKind::Dunes, Item::Water) => 0.6,
+            _ => 1.0,
+        };
+        let scale = population as f32 / 100.0;
+        let good = Good {
+            supply: supply * supply_factor * terrain_factor * scale,
+            demand: demand * demand_factor * scale,
+            base_price,
+            drift: 1.0,
+            pending: 0.0,
+        };
+        let amount = (good.target_stock() * stock_factor) as u32;
+        stock
+            .add(item, amount.min(stock.space_for(item)))
+            .expect("Stock is limited to what fits");
+        goods.insert(item, good);
+    }
+    let village = Village {
+        name,
+        population,
+        goods,
+        seed: rng.gen(),
+        ticks: 0,
+        since_tick: 0.0,
+    };
+    let wallet = Wallet(population * MAX_SAVINGS_PER_INHABITANT / 2);
+    (village, stock, wallet)
+}
+
+/// Components of a village, also used when loading a save
+pub fn village_bundle(
+    pos: RowEvenPos,
+    village: Village,
+    inventory: Inventory,
+    wallet: Wallet,
+) -> impl Bundle {
+    (
+        MapPos { pos, ..default() },
+        village,
+        inventory,
+        wallet,
+        TradePartner::default(),
+    )
+}
+
+/// Turns villages into entities when the platform or a trader first gets close to them
+fn visit_villages(
+    mut commands: Commands,
+    mut villages: ResMut<Villages>,
+    world_seed: Res<WorldSeed>,
+    generated_chunks: Res<GeneratedChunks>,
+    visitors: Query<&MapPos, Or<(With<MiningPlatform>, With<Trader>)>>,
+) {
+    let terrain = TerrainMap::new(&world_seed, &generated_chunks);
+    let distance = VISIT_DISTANCE as i32;
+    for map_pos in visitors.iter() {
+        for q in map_pos.pos.q - distance..=map_pos.pos.q + distance {
+            for r in map_pos.pos.r - distance..=map_pos.pos.r + distance {
+                let pos = RowEvenPos { q, r };
+                if hex_distance(pos, map_pos.pos) > VISIT_DISTANCE
+                    || villages.0.contains_key(&(q, r))
+                    || terrain.kind_at(pos) != TileKind::Village
+                {
+                    continue;
+                }
+                let (village, inventory, wallet) = generate_village(&world_seed, pos);
+                info!("Found the village of {}", village.name);
+                let entity = commands
+                    .spawn(village_bundle(pos, village, inventory, wallet))
+                    .id();
+                villages.0.insert((q, r), entity);
+            }
+        }
+    }
+}
+
+fn run_village_economies(
+    time: Res<Time>,
+    mut villages: Query<(&mut Village, &mut Inventory, &mut Wallet)>,
+) {
+    for (mut village, mut inventory, mut wallet) in villages.iter_mut() {
+        village.since_tick += time.delta_seconds();
+        while village.since_tick >= ECONOMY_TICK {
+            village.since_tick -= ECONOMY_TICK;
+            village.tick(&mut inventory, &mut wallet);
+        }
+    }
+}
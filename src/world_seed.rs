@@ -0,0 +1,256 @@
+use std::{fmt, str::FromStr};
+
+use super::{config::Config, ChunkPos};
+use bevy::prelude::*;
+use rand::prelude::*;
+use rand_chacha::ChaCha8Rng;
+use serde::{Deserialize, Serialize};
+
+/// Algorithm used to derive chunk seeds from the world seed. It is saved along with the world, so
+/// when the algorithm has to change, add a new version and keep the old ones to keep old worlds
+/// the same.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum SeedDerivation {
+    /// Worlds from before seeds were derived. Chunk terrain comes from `SmallRng` seeded with the
+    /// world seed whose last 8 bytes are replaced by chunk x and y as little endian, everything
+    /// that didn't exist yet is derived as in V1.
+    Legacy,
+    /// BLAKE3 in key derivation mode. Context string is picked by purpose, key material is the
+    /// world seed followed by chunk x and y as little endian, or just the world seed for things
+    /// that are not per chunk.
+    V1,
+    /// Same derivation as V1, with terrain from noise instead of tiles rolled one by one
+    #[default]
+    V2,
+}
+
+impl SeedDerivation {
+    /// Prefix of seeds written for this derivation, the default derivation is written without one
+    fn prefix(self) -> Option<&'static str> {
+        match self {
+            Self::Legacy => Some("legacy"),
+            Self::V1 => Some("v1"),
+            Self::V2 => None,
+        }
+    }
+
+    /// Whether the terrain of the world comes from [`TerrainNoise`](crate::terrain::TerrainNoise),
+    /// older worlds are open sand with scattered villages
+    pub fn has_terrain_noise(self) -> bool {
+        matches!(self, Self::V2)
+    }
+}
+
+/// What a derived seed is used for, so that each generation pass gets its own random stream
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SeedPurpose {
+    /// Tiles of V1 worlds, rolled one by one
+    Terrain,
+    /// Noise that spans the whole world, not derived per chunk
+    TerrainNoise,
+    Villages,
+    Deposits,
+    /// Traders that roam the whole world
+    Traders,
+    /// Population and goods of villages, one stream per tile
+    VillageEconomies,
+    /// Raider gangs, one stream per raid
+    Raiders,
+    /// Weather, one stream per sandstorm
+    Sandstorms,
+    /// Npcs other than traders and raiders. None are generated yet, the context string is
+    /// reserved so that it doesn't change once they are.
+    #[allow(dead_code)]
+    Npcs,
+}
+
+impl SeedPurpose {
+    /// Context string for BLAKE3 key derivation. Must never change for existing purposes.
+    fn v1_context(self) -> &'static str {
+        match self {
+            Self::Terrain => "sands_of_merkhyl v1 chunk terrain",
+            Self::TerrainNoise => "sands_of_merkhyl v1 terrain noise",
+            Self::Villages => "sands_of_merkhyl v1 chunk villages",
+            Self::Deposits => "sands_of_merkhyl v1 chunk deposits",
+            Self::Traders => "sands_of_merkhyl v1 traders",
+            Self::VillageEconomies => "sands_of_merkhyl v1 chunk village economies",
+            Self::Raiders => "sands_of_merkhyl v1 raiders",
+            Self::Sandstorms => "sands_of_merkhyl v1 sandstorms",
+            Self::Npcs => "sands_of_merkhyl v1 npcs",
+        }
+    }
+}
+
+#[derive(Resource, Debug, Clone, PartialEq, Eq)]
+pub struct WorldSeed {
+    pub seed: [u8; 32],
+    pub derivation: SeedDerivation,
+}
+
+impl WorldSeed {
+    fn random() -> Self {
+        WorldSeed {
+            seed: thread_rng().gen(),
+            derivation: SeedDerivation::default(),
+        }
+    }
+
+    /// Seed for things that are generated for the whole world at once
+    pub fn world_seed(&self, purpose: SeedPurpose) -> [u8; 32] {
+        match self.derivation {
+            SeedDerivation::Legacy | SeedDerivation::V1 | SeedDerivation::V2 => {
+                blake3::derive_key(purpose.v1_context(), &self.seed)
+            }
+        }
+    }
+
+    pub fn chunk_seed(&self, chunk_pos: ChunkPos, purpose: SeedPurpose) -> [u8; 32] {
+        match self.derivation {
+            SeedDerivation::Legacy if purpose == SeedPurpose::Terrain => {
+                let mut chunk_seed = self.seed;
+                chunk_seed[24..28].copy_from_slice(&chunk_pos.x.to_le_bytes());
+                chunk_seed[28..32].copy_from_slice(&chunk_pos.y.to_le_bytes());
+                chunk_seed
+            }
+            SeedDerivation::Legacy | SeedDerivation::V1 | SeedDerivation::V2 => {
+                let mut hasher = blake3::Hasher::new_derive_key(purpose.v1_context());
+                hasher.update(&self.seed);
+                hasher.update(&chunk_pos.x.to_le_bytes());
+                hasher.update(&chunk_pos.y.to_le_bytes());
+                *hasher.finalize().as_bytes()
+            }
+        }
+    }
+
+    /// Random generator for a chunk. ChaCha is used because, unlike `SmallRng`, it is guaranteed
+    /// to give the same numbers on every platform and version.
+    pub fn chunk_rng(&self, chunk_pos: ChunkPos, purpose: SeedPurpose) -> ChaCha8Rng {
+        ChaCha8Rng::from_seed(self.chunk_seed(chunk_pos, purpose))
+    }
+}
+
+impl FromWorld for WorldSeed {
+    fn from_world(world: &mut World) -> Self {
+        let world_seed = match &world.resource::<Config>().seed {
+            Some(seed) => seed
+                .parse()
+                .expect("Seed is checked when the config is loaded"),
+            None => Self::random(),
+        };
+        info!(
+            "World seed is {0}, start with `--seed {0}` to play it again",
+            world_seed
+        );
+        world_seed
+    }
+}
+
+/// Seed is written as 64 hex digits, prefixed like `legacy:` or `v1:` when the world is derived
+/// the old way
+impl fmt::Display for WorldSeed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(prefix) = self.derivation.prefix() {
+            write!(f, "{}:", prefix)?;
+        }
+        for byte in self.seed {
+            write!(f, "{:02X}", byte)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+pub enum ParseSeedError {
+    Length(usize),
+    InvalidDigit,
+    UnknownDerivation(String),
+}
+
+impl fmt::Display for ParseSeedError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Length(len) => write!(f, "expected 64 hex digits, got {}", len),
+            Self::InvalidDigit => write!(f, "not a hex digit"),
+            Self::UnknownDerivation(prefix) => write!(
+                f,
+                "unknown derivation {:?}, expected \"legacy\" or \"v1\"",
+                prefix
+            ),
+        }
+    }
+}
+
+impl FromStr for WorldSeed {
+    type Err = ParseSeedError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let (derivation, s) = match s.split_once(':') {
+            Some((prefix, s)) => {
+                let derivation = [SeedDerivation::Legacy, SeedDerivation::V1]
+                    .into_iter()
+                    .find(|derivation| derivation.prefix() == Some(prefix))
+                    .ok_or_else(|| ParseSeedError::UnknownDerivation(prefix.to_string()))?;
+                (derivation, s)
+            }
+            None => (SeedDerivation::default(), s),
+        };
+        if s.len() != 64 {
+            return Err(ParseSeedError::Length(s.len()));
+        }
+        if !s.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return Err(ParseSeedError::InvalidDigit);
+        }
+        let mut seed = [0; 32];
+        for (byte, digits) in seed.iter_mut().zip(s.as_bytes().chunks(2)) {
+            *byte = u8::from_str_radix(std::str::from_utf8(digits).unwrap(), 16).unwrap();
+        }
+        Ok(WorldSeed { seed, derivation })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn legacy_terrain_seed_is_spliced_with_chunk_pos() {
+        let legacy = WorldSeed {
+            seed: [0xAB; 32],
+            derivation: SeedDerivation::Legacy,
+        };
+        let chunk_pos = ChunkPos::new(1, -2);
+        let chunk_seed = legacy.chunk_seed(chunk_pos, SeedPurpose::Terrain);
+        assert_eq!(chunk_seed[..24], [0xAB; 24]);
+        assert_eq!(chunk_seed[24..28], 1i32.to_le_bytes());
+        assert_eq!(chunk_seed[28..32], (-2i32).to_le_bytes());
+
+        // Everything else is derived as in V1
+        let v1 = WorldSeed {
+            derivation: SeedDerivation::V1,
+            ..legacy.clone()
+        };
+        assert_eq!(
+            legacy.chunk_seed(chunk_pos, SeedPurpose::Deposits),
+            v1.chunk_seed(chunk_pos, SeedPurpose::Deposits)
+        );
+    }
+
+    #[test]
+    fn seed_is_written_with_its_derivation() {
+        let hex = "552CD7D00FA463D80D52103BFCFD1974A4022E5F556354D50000000000000000";
+        for (written, derivation) in [
+            (format!("legacy:{}", hex), SeedDerivation::Legacy),
+            (format!("v1:{}", hex), SeedDerivation::V1),
+            (hex.to_string(), SeedDerivation::V2),
+        ] {
+            let world_seed: WorldSeed = written.parse().unwrap();
+            assert_eq!(world_seed.derivation, derivation);
+            assert_eq!(world_seed.to_string(), written);
+        }
+        assert!(matches!(
+            format!("v9:{}", hex).parse::<WorldSeed>(),
+            Err(ParseSeedError::UnknownDerivation(_))
+        ));
+    }
+}